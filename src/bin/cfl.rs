@@ -1,102 +1,440 @@
 use anyhow::{Context, Result};
-use cfl::{cli::Cli, CflBuilder, CflError};
+use cfl::cli::{
+    count_tokens_output, file_listing, home_dir, languages_output, split_path_list, summary_output,
+    Cli,
+};
+use cfl::clipboard::{ClipboardBackend, SystemClipboard};
+use cfl::color::Colors;
+use cfl::format::{files_metadata_json, format_cost, format_number_with, stats_json};
+use cfl::sink::{deliver, Sink};
+use cfl::{Estimate, FileProcessor, SkipReason};
 use clap::Parser;
-use clipboard::{ClipboardContext, ClipboardProvider};
-
-fn format_number(num: usize) -> String {
-    num.to_string()
-        .chars()
-        .rev()
-        .collect::<Vec<_>>()
-        .chunks(3)
-        .map(|chunk| chunk.iter().collect::<String>())
-        .collect::<Vec<_>>()
-        .join(",")
-        .chars()
-        .rev()
-        .collect()
+use log::{LevelFilter, Log, Metadata, Record};
+use std::io::{IsTerminal, Read};
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+#[cfg(unix)]
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+#[cfg(unix)]
+use std::sync::OnceLock;
+
+/// Minimal logger writing library diagnostics to stderr
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+fn init_logger(cli: &Cli) {
+    let level = if cli.verbose {
+        LevelFilter::Debug
+    } else if cli.quiet {
+        LevelFilter::Error
+    } else {
+        LevelFilter::Warn
+    };
+
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    init_logger(&cli);
+    cli.check_clipboard(|name| std::env::var(name).ok())?;
     let current_dir = std::env::current_dir().context("Failed to get current directory")?;
 
-    // パターンを事前に取得
-    let include_pattern = cli.include.as_deref().unwrap_or_default();
-    let exclude_pattern = cli.exclude.as_deref().unwrap_or_default();
+    cli.check_root_guard(&current_dir, home_dir().as_deref())?;
+
+    let paths = if cli.reads_paths_from_stdin() {
+        let mut input = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut input)
+            .context("Failed to read paths from stdin")?;
+        split_path_list(&input, cli.null)
+    } else {
+        cli.paths().into_iter().map(PathBuf::from).collect()
+    };
+
+    #[cfg(feature = "watch")]
+    let watching = cli.watch;
+    #[cfg(not(feature = "watch"))]
+    let watching = false;
+    let mut builder = cli.builder(&current_dir);
+    // --watch は Ctrl-C で終了させる
+    if !watching {
+        if let Some(flag) = install_interrupt_handler() {
+            builder = builder.interrupt_flag(flag);
+        }
+    }
+    let mut processor = builder.build()?;
+    if cli.explain_ignores {
+        return explain_ignores(&paths, &processor);
+    }
+    if cli.max_total_files_warning.is_some() {
+        preflight(&cli, &paths, &processor)?;
+    }
+    process_all(&cli, &paths, &mut processor)?;
+    if processor.is_interrupted() {
+        let stats = processor.stats();
+        eprintln!(
+            "\n⚠️  Interrupted: emitting the {} files ({} tokens) collected so far",
+            format_number_with(stats.files, cli.thousands_sep),
+            format_number_with(stats.total_tokens, cli.thousands_sep)
+        );
+        emit(&cli, &processor)?;
+        std::process::exit(130);
+    }
+    emit(&cli, &processor)?;
+
+    #[cfg(feature = "watch")]
+    if cli.watch {
+        let mut watched: Vec<&std::path::Path> = paths.iter().map(PathBuf::as_path).collect();
+        if !cli.from_glob.is_empty() {
+            watched.push(&current_dir);
+        }
+        // 自身の出力への書き込みで再実行しないようにする
+        let outputs = cli.written_paths(&current_dir);
+        eprintln!("\n👀 Watching for changes (Ctrl-C to stop)...");
+        cfl::watch::watch(
+            &watched,
+            &outputs,
+            std::time::Duration::from_millis(300),
+            || {
+                processor.clear();
+                process_all(&cli, &paths, &mut processor)?;
+                emit(&cli, &processor)
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Make the first Ctrl-C stop processing instead of the program, so the files collected
+/// so far are still emitted; a second Ctrl-C exits right away.
+///
+/// Returns `None` where signal handling isn't supported.
+#[cfg(unix)]
+fn install_interrupt_handler() -> Option<Arc<AtomicBool>> {
+    static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
 
-    let mut processor = CflBuilder::new()
-        .include_patterns(include_pattern)
-        .exclude_patterns(exclude_pattern)
-        .current_dir(&current_dir)
-        .build()?;
+    extern "C" fn on_interrupt(_signal: libc::c_int) {
+        if FLAG
+            .get()
+            .is_some_and(|flag| flag.swap(true, Ordering::SeqCst))
+        {
+            // SAFETY: _exit is async-signal-safe
+            unsafe { libc::_exit(130) };
+        }
+    }
+
+    let flag = FLAG
+        .get_or_init(|| Arc::new(AtomicBool::new(false)))
+        .clone();
+    let handler: extern "C" fn(libc::c_int) = on_interrupt;
+    // SAFETY: the handler only touches an atomic flag and calls _exit
+    let previous = unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+    (previous != libc::SIG_ERR).then_some(flag)
+}
+
+#[cfg(not(unix))]
+fn install_interrupt_handler() -> Option<Arc<AtomicBool>> {
+    None
+}
+
+/// Print the ignore rule hiding each path, if any
+fn explain_ignores(paths: &[PathBuf], processor: &FileProcessor) -> Result<()> {
+    for path in paths {
+        match processor.ignore_reason(path)? {
+            Some(rule) => println!("{}: ignored by {}", path.display(), rule),
+            None => println!("{}: not ignored", path.display()),
+        }
+    }
+    Ok(())
+}
+
+/// Count the files to process and warn (or stop) when there are too many
+fn preflight(cli: &Cli, paths: &[PathBuf], processor: &FileProcessor) -> Result<()> {
+    let mut estimate = Estimate::default();
+    if !cli.from_glob.is_empty() {
+        let globs: Vec<&str> = cli.from_glob.iter().map(String::as_str).collect();
+        estimate = processor
+            .estimate_globs(&globs)
+            .context("Failed to process --from-glob")?;
+    }
+    for path in paths {
+        #[cfg(feature = "git")]
+        let path_estimate = match &cli.git_ref {
+            Some(rev) => processor.estimate_ref(rev, path),
+            None => processor.estimate(path),
+        };
+        #[cfg(not(feature = "git"))]
+        let path_estimate = processor.estimate(path);
+        let path_estimate =
+            path_estimate.with_context(|| format!("Failed to process path: {}", path.display()))?;
+        estimate.files += path_estimate.files;
+        estimate.total_size += path_estimate.total_size;
+    }
+
+    if let Some(warning) = cli.check_preflight(&estimate)? {
+        let colors = Colors::new(cli.color_enabled(std::io::stderr().is_terminal(), |name| {
+            std::env::var(name).ok()
+        }));
+        eprintln!("⚠️  {}", colors.warning(&warning));
+    }
+    Ok(())
+}
 
-    for path in cli.paths.split(',') {
+/// Process the globs given on the command line and every path in `paths`
+fn process_all(cli: &Cli, paths: &[PathBuf], processor: &mut FileProcessor) -> Result<()> {
+    if !cli.from_glob.is_empty() {
+        let globs: Vec<&str> = cli.from_glob.iter().map(String::as_str).collect();
+        processor
+            .process_globs(&globs)
+            .context("Failed to process --from-glob")?;
+    }
+    for path in paths {
+        if processor.is_interrupted() {
+            break;
+        }
+        #[cfg(feature = "git")]
+        if let Some(rev) = &cli.git_ref {
+            processor.process_ref(rev, path).with_context(|| {
+                format!("Failed to process path: {} at {}", path.display(), rev)
+            })?;
+            continue;
+        }
         processor
-            .process_path(std::path::Path::new(path))
-            .with_context(|| format!("Failed to process path: {}", path))?;
+            .process_path(path)
+            .with_context(|| format!("Failed to process path: {}", path.display()))?;
     }
+    Ok(())
+}
 
+/// Print, write or copy the result as selected on the command line
+fn emit(cli: &Cli, processor: &FileProcessor) -> Result<()> {
     let target_files = processor.get_target_files();
     let files_count = target_files.len();
+    let number = |num: usize| format_number_with(num, cli.thousands_sep);
+    let colors = Colors::new(cli.color_enabled(std::io::stdout().is_terminal(), |name| {
+        std::env::var(name).ok()
+    }));
+
+    if let Some(path) = &cli.stats_json {
+        let json = stats_json(
+            &processor.stats(),
+            target_files,
+            processor.get_skipped_files(),
+        );
+        std::fs::write(path, json)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
 
-    if cli.show {
+    if cli.count_tokens_only {
+        println!("{}", count_tokens_output(processor));
+    } else if cli.file_list {
+        print!("{}", processor.get_file_list());
+    } else if cli.cost_tree {
+        print!("{}", processor.get_cost_tree());
+        println!(
+            "\n🔤 Total: {} tokens",
+            colors.number(&number(processor.get_total_tokens()))
+        );
+    } else if cli.summary_only {
+        print!("{}", summary_output(&processor.stats(), cli.thousands_sep));
+    } else if cli.list_languages {
+        print!(
+            "{}",
+            languages_output(&processor.stats(), cli.thousands_sep)
+        );
+    } else if cli.show_json {
+        println!("{}", files_metadata_json(target_files));
+    } else if cli.show {
         println!("📋 Target files:");
-        for file in target_files {
+        for (file, preview) in processor.get_previews(cli.preview.unwrap_or(0)) {
             println!(
                 "  • {} ({} bytes, {} tokens)",
                 file.path,
-                format_number(file.size),
-                format_number(file.tokens)
+                number(file.size),
+                number(file.tokens)
             );
+            for line in preview {
+                println!("      │ {}", line);
+            }
         }
-        println!("\n📊 Total: {} files", format_number(files_count));
+        println!("\n📊 Total: {} files", colors.number(&number(files_count)));
+        print_top_files(cli, processor);
     } else {
-        let mut ctx: ClipboardContext =
-            ClipboardProvider::new().map_err(|e| CflError::Clipboard(e.to_string()))?;
-
-        ctx.set_contents(processor.get_result().to_string())
-            .map_err(|e| CflError::Clipboard(e.to_string()))?;
+        let destination = match &cli.output_dir {
+            Some(output_dir) => {
+                processor
+                    .write_output_dir(output_dir)
+                    .with_context(|| format!("Failed to write to {}", output_dir.display()))?;
+                format!("{}/", output_dir.display())
+            }
+            None => {
+                let sinks = cli.sinks();
+                #[cfg(feature = "clipboard-html")]
+                let html = cli.clipboard_html.then(|| processor.get_html());
+                #[cfg(not(feature = "clipboard-html"))]
+                let html: Option<String> = None;
+                let clipboard = deliver_result(processor, &sinks, html.as_deref())?;
+                // 標準出力に結果を書いた場合は要約を混ぜない
+                if sinks.contains(&Sink::Stdout) {
+                    return Ok(());
+                }
+                sinks
+                    .iter()
+                    .map(|sink| match sink {
+                        Sink::File(path) => path.display().to_string(),
+                        _ => clipboard.clone(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" and ")
+            }
+        };
 
         println!(
-            "\n✨ Successfully copied {} files to clipboard:",
-            files_count
+            "\n✨ Successfully copied {} files to {}:",
+            colors.number(&files_count.to_string()),
+            destination
         );
         println!("📁 Files:");
-        for file in target_files {
-            println!(
-                "  • {} ({} bytes, {} tokens)",
-                file.path,
-                format_number(file.size),
-                format_number(file.tokens)
-            );
-        }
+        print!(
+            "{}",
+            file_listing(target_files, cli.list_limit, cli.thousands_sep)
+        );
 
         let total_size = processor.get_total_size();
         let total_tokens = processor.get_total_tokens();
 
         println!("\n📊 Summary:");
-        println!("  📂 Total files: {}", format_number(files_count));
-        println!("  📦 Total size: {} bytes", format_number(total_size));
-        println!("  🔤 Total tokens: {}", format_number(total_tokens));
+        println!("  📂 Total files: {}", colors.number(&number(files_count)));
+        println!(
+            "  📦 Total size: {} bytes",
+            colors.number(&number(total_size))
+        );
+        println!(
+            "  🔤 Total tokens: {}",
+            colors.number(&number(total_tokens))
+        );
+        if let Some(price) = cli.price_per_1k_tokens {
+            println!(
+                "  💰 Estimated input cost: {}",
+                colors.number(&format_cost(total_tokens, price))
+            );
+        }
+        print_top_files(cli, processor);
 
         println!("\n📁 Directory Structure:");
         let structure = processor.get_directory_structure()?;
         println!("{}", structure);
 
-        if let Some(include) = &cli.include {
-            println!("  🎯 Include patterns: {}", include);
+        if !cli.include.is_empty() {
+            println!("  🎯 Include patterns: {}", cli.include.join(","));
         }
-        if let Some(exclude) = &cli.exclude {
-            println!("  🚫 Exclude patterns: {}", exclude);
+        if !cli.exclude.is_empty() {
+            println!("  🚫 Exclude patterns: {}", cli.exclude.join(","));
         }
 
+        if processor.is_truncated() {
+            println!(
+                "\n⚠️  {}",
+                colors.warning("The output was truncated at --max-output-bytes.")
+            );
+        }
+        let omitted: Vec<&str> = processor
+            .get_skipped_files()
+            .iter()
+            .filter(|file| file.reason == SkipReason::LanguageTokenLimit)
+            .map(|file| file.path.as_str())
+            .collect();
+        if !omitted.is_empty() {
+            println!(
+                "\n⚠️  {}",
+                colors.warning(&format!(
+                    "{} files were omitted by --max-tokens-per-lang:",
+                    omitted.len()
+                ))
+            );
+            for path in omitted {
+                println!("  • {}", path);
+            }
+        }
         if files_count == 0 {
-            println!("\n⚠️  No files were copied. Check your include/exclude patterns.");
+            println!(
+                "\n⚠️  {}",
+                colors.warning("No files were copied. Check your include/exclude patterns.")
+            );
         } else {
-            println!("\n✅ Copy completed successfully!");
+            println!("\n✅ {}", colors.success("Copy completed successfully!"));
         }
     }
 
     Ok(())
 }
+
+/// Write the result to each of `sinks`, with `html` alongside it on the clipboard,
+/// returning how the clipboard is described in the summary
+fn deliver_result(processor: &FileProcessor, sinks: &[Sink], html: Option<&str>) -> Result<String> {
+    let mut stdout = std::io::stdout();
+
+    #[cfg(feature = "clipboard-html")]
+    if html.is_some() && sinks.contains(&Sink::Clipboard) {
+        let mut clipboard = cfl::clipboard::HtmlClipboard::new()?;
+        deliver(
+            processor.get_result(),
+            html,
+            sinks,
+            &mut stdout,
+            &mut clipboard,
+        )?;
+        return Ok("clipboard (text and HTML)".to_string());
+    }
+
+    let mut clipboard = SystemClipboard::default();
+    deliver(
+        processor.get_result(),
+        html,
+        sinks,
+        &mut stdout,
+        &mut clipboard,
+    )?;
+    Ok(match clipboard.backend() {
+        Some(ClipboardBackend::Wayland) => "clipboard (wl-copy)".to_string(),
+        _ => "clipboard".to_string(),
+    })
+}
+
+/// Print the files with the most tokens when `--top` is given
+fn print_top_files(cli: &Cli, processor: &FileProcessor) {
+    let Some(n) = cli.top else {
+        return;
+    };
+    let number = |num: usize| format_number_with(num, cli.thousands_sep);
+
+    println!("\n🏆 Top {} files by tokens:", n);
+    for file in processor.top_files_by_tokens(n) {
+        println!(
+            "  • {} ({} tokens, {} bytes)",
+            file.path,
+            number(file.tokens),
+            number(file.size)
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use glob::Pattern;
+use std::fs;
+use std::path::Path;
+
+/// Number of leading bytes inspected when sniffing for binary content
+const SNIFF_LEN: usize = 8000;
+
+/// Guess whether content is binary: contains a NUL byte near the start or isn't valid UTF-8
+pub(crate) fn looks_binary(bytes: &[u8]) -> bool {
+    has_leading_nul(bytes) || std::str::from_utf8(bytes).is_err()
+}
+
+/// Check for a NUL byte near the start, which text in any supported encoding never has
+pub(crate) fn has_leading_nul(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(SNIFF_LEN)].contains(&0)
+}
+
+/// Sniff the type of content from its magic bytes and check whether it's a known
+/// non-text format (image, archive, font, ...). Unrecognized content isn't binary here.
+#[cfg(feature = "sniff")]
+pub(crate) fn sniffs_binary(bytes: &[u8]) -> bool {
+    infer::get(bytes).is_some_and(|kind| kind.matcher_type() != infer::MatcherType::Text)
+}
+
+/// Line length of base64 blocks, as in MIME
+const BASE64_LINE_LEN: usize = 76;
+
+/// Encode `bytes` as standard base64, wrapped at 76 characters per line
+pub(crate) fn base64_block(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = Vec::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        encoded.push(ALPHABET[n >> 18 & 63]);
+        encoded.push(ALPHABET[n >> 12 & 63]);
+        encoded.push(if chunk.len() > 1 {
+            ALPHABET[n >> 6 & 63]
+        } else {
+            b'='
+        });
+        encoded.push(if chunk.len() > 2 {
+            ALPHABET[n & 63]
+        } else {
+            b'='
+        });
+    }
+
+    let mut block = String::with_capacity(encoded.len() + encoded.len() / BASE64_LINE_LEN + 1);
+    for line in encoded.chunks(BASE64_LINE_LEN) {
+        block.push_str(std::str::from_utf8(line).unwrap_or_default());
+        block.push('\n');
+    }
+    block
+}
+
+/// Guess the MIME type of a binary file from its extension
+pub(crate) fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("bmp") => "image/bmp",
+        Some("ico") => "image/x-icon",
+        Some("pdf") => "application/pdf",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("wasm") => "application/wasm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// How `.gitattributes` classifies a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Classification {
+    Binary,
+    Text,
+}
+
+/// Look up the `binary`/`text` attributes declared for `path` in `.gitattributes` files.
+///
+/// Files are read from the repository root (the nearest ancestor containing `.git`)
+/// down to the file's directory; later and deeper declarations win, as in git.
+pub(crate) fn gitattributes_classification(path: &Path) -> Option<Classification> {
+    let dirs: Vec<&Path> = path.ancestors().skip(1).collect();
+    let end = dirs
+        .iter()
+        .position(|dir| dir.join(".git").exists())
+        .map_or(dirs.len(), |root| root + 1);
+
+    let mut classification = None;
+    for dir in dirs[..end].iter().rev() {
+        let Ok(attributes) = fs::read_to_string(dir.join(".gitattributes")) else {
+            continue;
+        };
+        let relative = path.strip_prefix(dir).unwrap_or(path).to_string_lossy();
+        for line in attributes.lines() {
+            if let Some(found) = classify_line(line, &relative) {
+                classification = Some(found);
+            }
+        }
+    }
+
+    classification
+}
+
+/// Classify `relative_path` according to a single `.gitattributes` line, if it applies
+fn classify_line(line: &str, relative_path: &str) -> Option<Classification> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let mut fields = line.split_whitespace();
+    let pattern = fields.next()?;
+    let matches = if pattern.contains('/') {
+        Pattern::new(pattern.trim_start_matches('/')).is_ok_and(|p| p.matches(relative_path))
+    } else {
+        let file_name = Path::new(relative_path)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+        Pattern::new(pattern).is_ok_and(|p| p.matches(file_name))
+    };
+    if !matches {
+        return None;
+    }
+
+    let mut classification = None;
+    for attribute in fields {
+        match attribute {
+            "binary" | "-text" => classification = Some(Classification::Binary),
+            "text" | "-binary" => classification = Some(Classification::Text),
+            _ if attribute.starts_with("text=") => classification = Some(Classification::Text),
+            _ => {}
+        }
+    }
+    classification
+}
@@ -1,4 +1,17 @@
-use clap::{command, Parser};
+use crate::error::CflError;
+use crate::format::{format_number, format_number_with, OutputFormat, SortKey};
+#[cfg(feature = "notebook")]
+use crate::notebook::NotebookCells;
+use crate::processor::{
+    canonicalize_lenient, Estimate, FileInfo, FileProcessor, LongLineAction, OnError, Stats,
+    TokenModel,
+};
+use crate::sink::Sink;
+use crate::{CflBuilder, GitInfo};
+use clap::{Parser, ValueEnum};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(
@@ -19,6 +32,7 @@ use clap::{command, Parser};
 
     # Copy both Rust and TOML files
     cfl . -i \"*.rs,*.toml\"
+    cfl . -i \"*.rs\" -i \"*.toml\"
 
     # Copy all files except JSON files
     cfl . -e \"*.json\"
@@ -31,33 +45,1344 @@ use clap::{command, Parser};
 
     # Show which files would be copied without copying
     cfl -s .
+
+    # Group the output by top-level directory
+    cfl . --group-by-dir
+
+    # Read the paths from stdin, separated by NUL bytes
+    git ls-files -z | cfl - -0
+
+    # Write the result to a file instead of the clipboard
+    cfl . -o context.md
     
 Note: .gitignore rules are automatically respected"
 )]
 pub struct Cli {
     /// Paths to copy (comma-separated)
-    #[arg(name = "PATHS", help = "Paths to copy (comma-separated)")]
-    pub paths: String,
+    #[arg(
+        name = "PATHS",
+        required_unless_present = "from_glob",
+        help = "Paths to copy (comma-separated), or - to read them from stdin, one per line"
+    )]
+    pub paths: Option<String>,
+
+    /// Paths on stdin are NUL-separated
+    #[arg(
+        short = '0',
+        long,
+        help = "Paths read from stdin are separated by NUL bytes (as from find -print0 or git ls-files -z)"
+    )]
+    pub null: bool,
+
+    /// Globs selecting the files to copy
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Copy the files matching GLOB (e.g. \"src/**/*.rs\") below the current directory; repeatable"
+    )]
+    pub from_glob: Vec<String>,
 
-    /// Include patterns (comma-separated)
+    /// Include patterns (comma-separated, repeatable)
     #[arg(
         short,
         long,
-        help = "Include only files matching these patterns (comma-separated)",
+        help = "Include only files matching these patterns (comma-separated); repeatable",
         value_name = "PATTERNS"
     )]
-    pub include: Option<String>,
+    pub include: Vec<String>,
 
-    /// Exclude patterns (comma-separated)
+    /// Exclude patterns (comma-separated, repeatable)
     #[arg(
         short,
         long,
-        help = "Exclude files matching these patterns (comma-separated)",
+        help = "Exclude files matching these patterns (comma-separated); repeatable",
         value_name = "PATTERNS"
     )]
-    pub exclude: Option<String>,
+    pub exclude: Vec<String>,
+
+    /// File names included regardless of the include patterns (comma-separated)
+    #[arg(
+        long,
+        help = "Include files with these exact names (e.g. Makefile,Dockerfile) regardless of --include",
+        value_name = "NAMES"
+    )]
+    pub include_names: Option<String>,
+
+    /// Exclude common noise directories
+    #[arg(
+        long,
+        help = "Also exclude common build, dependency and cache directories (target, node_modules, dist, .venv, __pycache__, ...)"
+    )]
+    pub sensible_defaults: bool,
+
+    /// Explain which ignore rules hide the paths
+    #[arg(
+        long,
+        help = "Print the ignore file and line hiding each of PATHS instead of copying them"
+    )]
+    pub explain_ignores: bool,
 
     /// Show target files (relative paths)
     #[arg(short, long, help = "Show which files would be copied without copying")]
     pub show: bool,
+
+    /// Show target files as JSON metadata
+    #[arg(
+        long,
+        conflicts_with = "show",
+        help = "Print the target files as a JSON array of metadata (path, size, tokens, language) without copying"
+    )]
+    pub show_json: bool,
+
+    /// Print only the total token count
+    #[arg(
+        long,
+        conflicts_with_all = ["show", "show_json"],
+        help = "Print only the total token count, without copying"
+    )]
+    pub count_tokens_only: bool,
+
+    /// Print the target files as a flat list
+    #[arg(
+        long,
+        conflicts_with_all = ["show", "show_json", "count_tokens_only"],
+        help = "Print the relative path of each target file, one per line, without copying"
+    )]
+    pub file_list: bool,
+
+    /// Print the token cost of each file and directory as a tree
+    #[arg(
+        long,
+        conflicts_with_all = ["show", "show_json", "count_tokens_only", "file_list"],
+        help = "Print the tree of target files with the tokens of each file and directory, without copying"
+    )]
+    pub cost_tree: bool,
+
+    /// Print only the statistics of the target files
+    #[arg(
+        long,
+        conflicts_with_all = ["show", "show_json", "count_tokens_only", "file_list", "cost_tree"],
+        help = "Print the file count, size, tokens and languages of the target files without building or copying the output"
+    )]
+    pub summary_only: bool,
+
+    /// Print the detected languages of the target files
+    #[arg(
+        long,
+        conflicts_with_all = ["show", "show_json", "count_tokens_only", "file_list", "cost_tree", "summary_only"],
+        help = "Print the languages detected among the target files with the number of files of each, without copying"
+    )]
+    pub list_languages: bool,
+
+    /// Print the result to stdout instead of copying it
+    #[arg(
+        long,
+        help = "Print the result to stdout instead of copying it to the clipboard"
+    )]
+    pub stdout: bool,
+
+    /// Write the result to a file instead of copying it
+    #[arg(
+        short,
+        long,
+        value_name = "FILE",
+        help = "Write the result to FILE instead of copying it to the clipboard"
+    )]
+    pub output: Option<PathBuf>,
+
+    /// Copy to the clipboard as well
+    #[arg(
+        long,
+        conflicts_with = "no_clipboard",
+        help = "Also copy the result to the clipboard when --stdout or --output is given"
+    )]
+    pub clipboard: bool,
+
+    /// Copy HTML alongside the plain text
+    #[cfg(feature = "clipboard-html")]
+    #[arg(
+        long,
+        help = "Also put an HTML rendering with <pre> blocks on the clipboard, for rich editors"
+    )]
+    pub clipboard_html: bool,
+
+    /// Allow copying the filesystem root or home directory
+    #[arg(
+        long,
+        visible_alias = "yes",
+        help = "Allow copying the filesystem root or your home directory"
+    )]
+    pub force: bool,
+
+    /// Read files from a git revision instead of the working tree
+    #[cfg(feature = "git")]
+    #[arg(
+        long = "ref",
+        value_name = "REV",
+        help = "Read the files from the tree of a git revision (e.g. HEAD~1) instead of the working tree"
+    )]
+    pub git_ref: Option<String>,
+
+    /// Preflight threshold on the number of files
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Before reading anything, warn when more than N files would be processed"
+    )]
+    pub max_total_files_warning: Option<usize>,
+
+    /// Stop at the preflight warning
+    #[arg(
+        long,
+        requires = "max_total_files_warning",
+        help = "Stop instead of warning when --max-total-files-warning is exceeded, unless --force is given"
+    )]
+    pub preflight_strict: bool,
+
+    /// Only copy staged files
+    #[cfg(feature = "git")]
+    #[arg(
+        long,
+        help = "Only copy files with changes staged in the git index (deleted files are skipped)"
+    )]
+    pub staged: bool,
+
+    /// Re-run on file changes
+    #[cfg(feature = "watch")]
+    #[arg(
+        long,
+        help = "Keep running and copy or write the result again whenever a file changes"
+    )]
+    pub watch: bool,
+
+    /// Write statistics as JSON to a sidecar file
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Also write the statistics of the run (files, totals, languages, skipped files) as JSON to PATH"
+    )]
+    pub stats_json: Option<PathBuf>,
+
+    /// Write one file per source into a mirror tree
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["stdout", "output"],
+        help = "Write each file's block to DIR/<path>.md instead of copying the combined result"
+    )]
+    pub output_dir: Option<PathBuf>,
+
+    /// Never touch the clipboard
+    #[arg(
+        long,
+        help = "Never use the clipboard; requires --stdout or --output (also set by CFL_NO_CLIPBOARD=1)"
+    )]
+    pub no_clipboard: bool,
+
+    /// Plain terminal output
+    #[arg(
+        long,
+        help = "Don't colorize the terminal summary (also set by NO_COLOR; off when not a terminal)"
+    )]
+    pub no_color: bool,
+
+    /// Thousands separator of numbers in the terminal summary
+    #[arg(
+        long,
+        value_name = "CHAR",
+        default_value_t = ',',
+        help = "Separator between groups of three digits in the terminal summary (e.g. '.' or ' ')"
+    )]
+    pub thousands_sep: char,
+
+    /// Preview each file in the listing
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "show",
+        help = "With --show, also print the first N lines of each file"
+    )]
+    pub preview: Option<usize>,
+
+    /// Cap on the files listed in the summary
+    #[arg(
+        long,
+        value_name = "N",
+        help = "List at most N files in the summary after copying (all files are still copied)"
+    )]
+    pub list_limit: Option<usize>,
+
+    /// Show the largest files
+    #[arg(
+        long,
+        value_name = "N",
+        help = "List the N files with the most tokens in the summary"
+    )]
+    pub top: Option<usize>,
+
+    /// Group output by top-level directory
+    #[arg(long, help = "Group the output into sections per top-level directory")]
+    pub group_by_dir: bool,
+
+    /// Normalize content per .editorconfig
+    #[arg(
+        long,
+        help = "Normalize final newlines and trailing whitespace per the nearest .editorconfig"
+    )]
+    pub editorconfig: bool,
+
+    /// Verbose logging
+    #[arg(
+        short,
+        long,
+        help = "Print debug diagnostics to stderr",
+        conflicts_with = "quiet"
+    )]
+    pub verbose: bool,
+
+    /// Quiet logging
+    #[arg(short, long, help = "Only print errors to stderr")]
+    pub quiet: bool,
+
+    /// Collapse import blocks
+    #[arg(
+        long,
+        help = "Replace runs of import statements (Rust, Python, JS/TS) with a single line"
+    )]
+    pub collapse_imports: bool,
+
+    /// Honor .dockerignore
+    #[arg(
+        long,
+        help = "Also skip files matched by .dockerignore, in addition to .gitignore"
+    )]
+    pub dockerignore: bool,
+
+    /// Preserve CRLF line endings
+    #[arg(
+        long,
+        help = "Keep \\r\\n and \\r line endings instead of converting them to \\n"
+    )]
+    pub keep_line_endings: bool,
+
+    /// Show symlink targets in headers
+    #[arg(
+        long,
+        help = "Show symlinked files at the real path of their target instead of the symlink's path"
+    )]
+    pub resolve_symlinks_in_paths: bool,
+
+    /// Leave paths out of the blocks
+    #[arg(
+        long,
+        help = "Head each code block with the file's language instead of its path"
+    )]
+    pub no_paths: bool,
+
+    /// Per-file block template
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = parse_template_file,
+        help = "Render each file's block with the template in FILE, using {path}, {content} and {lang} placeholders"
+    )]
+    pub template_file: Option<(PathBuf, String)>,
+
+    /// Put the path in a comment at the top of each block
+    #[arg(
+        long,
+        help = "Start each block with a comment giving the file's path (// path, # path, ...) in the file's language"
+    )]
+    pub path_comment: bool,
+
+    /// Classify binary files via .gitattributes
+    #[arg(
+        long,
+        help = "Use binary/text declarations in .gitattributes to decide which files to skip"
+    )]
+    pub respect_binary_gitattributes: bool,
+
+    /// Fail on binary files
+    #[arg(
+        long,
+        help = "Fail with an error naming the file instead of skipping binary files"
+    )]
+    pub fail_on_binary: bool,
+
+    /// Skip files sniffed as binary
+    #[cfg(feature = "sniff")]
+    #[arg(
+        long,
+        help = "Skip files whose content identifies a binary format (image, archive, ...), even with --encoding or --embed-binary-under"
+    )]
+    pub text_only: bool,
+
+    /// Show empty directories in the structure
+    #[arg(
+        long,
+        help = "Include directories without files in the directory structure"
+    )]
+    pub show_empty_dirs: bool,
+
+    /// Include tracked files matched by ignore rules
+    #[cfg(feature = "git")]
+    #[arg(
+        long,
+        help = "Include files tracked by git even if a .gitignore rule matches them"
+    )]
+    pub include_gitignored_but_tracked: bool,
+
+    /// List directories before files in the directory structure
+    #[arg(
+        long,
+        help = "List directories before files at each level of the directory structure"
+    )]
+    pub dirs_first: bool,
+
+    /// Walk directories in filesystem order
+    #[arg(
+        long,
+        help = "Read files in filesystem order instead of sorted by path; the output order may then differ between runs and machines"
+    )]
+    pub unsorted: bool,
+
+    /// Keep explicitly listed files in argument order
+    #[arg(
+        long,
+        help = "Emit explicitly listed files first, in the order they were given"
+    )]
+    pub preserve_arg_order: bool,
+
+    /// Output format
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Markdown,
+        help = "Output format of each file block"
+    )]
+    pub format: OutputFormat,
+
+    /// Shorthand for --format raw
+    #[arg(
+        long,
+        conflicts_with = "format",
+        help = "Emit raw content with `// ==== path ====` banners instead of code blocks (same as --format raw)"
+    )]
+    pub raw: bool,
+
+    /// Append content hashes
+    #[arg(long, help = "Add a short SHA-256 content hash to each file header")]
+    pub hash: bool,
+
+    /// Annotate each block with its size and tokens
+    #[arg(
+        long,
+        help = "Add a comment with the size and token count above each file block"
+    )]
+    pub annotate: bool,
+
+    /// Append a stats footer
+    #[arg(
+        long,
+        help = "Append a summary of file count, tokens and languages to the copied content"
+    )]
+    pub footer_stats: bool,
+
+    /// Price per 1,000 input tokens
+    #[arg(
+        long,
+        value_name = "DOLLARS",
+        help = "Show the estimated input cost at DOLLARS per 1,000 tokens in the summary (and in --footer-stats)"
+    )]
+    pub price_per_1k_tokens: Option<f64>,
+
+    /// Displayed path renames
+    #[arg(
+        long,
+        value_name = "FROM=TO",
+        value_parser = parse_rename,
+        help = "Show a file or directory path as another name in the output (repeatable)"
+    )]
+    pub rename: Vec<(String, String)>,
+
+    /// Project-local paths
+    #[arg(
+        long,
+        help = "Show paths relative to each file's nearest Cargo.toml or package.json directory"
+    )]
+    pub project_relative: bool,
+
+    /// Repository-root-relative paths
+    #[arg(
+        long,
+        conflicts_with = "project_relative",
+        help = "Show paths relative to the git repository root instead of the current directory"
+    )]
+    pub repo_relative: bool,
+
+    /// Prepend the git branch and commit
+    #[arg(
+        long,
+        help = "Start the copied content with the current git branch and commit, if in a repository"
+    )]
+    pub with_git_info: bool,
+
+    /// Record the invocation in the output
+    #[arg(
+        long,
+        help = "Start the copied content with a block recording the cfl command that produced it"
+    )]
+    pub embed_command: bool,
+
+    /// Global size limit
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Skip files larger than SIZE (e.g. 100k, 2m)"
+    )]
+    pub max_size: Option<usize>,
+
+    /// Per-extension size limits
+    #[arg(
+        long,
+        value_name = "EXT=SIZE,...",
+        value_parser = parse_size_limits,
+        help = "Size limits per extension, overriding --max-size (e.g. \"json=50k,lock=0\")"
+    )]
+    pub max_size_for: Option<HashMap<String, usize>>,
+
+    /// Only recently modified files
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        help = "Copy only files modified within DURATION (e.g. 30m, 24h, 7d)"
+    )]
+    pub modified_within: Option<Duration>,
+
+    /// Per-language token limits
+    #[arg(
+        long,
+        value_name = "LANG=N,...",
+        value_parser = parse_token_limits,
+        help = "Token limits per language; files that would exceed their language's limit are skipped (e.g. \"typescript=5000\")"
+    )]
+    pub max_tokens_per_lang: Option<HashMap<String, usize>>,
+
+    /// Maximum line length
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Handle files with lines longer than N characters according to --long-lines"
+    )]
+    pub max_line_length: Option<usize>,
+
+    /// Action for overly long lines
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = LongLineAction::Truncate,
+        requires = "max_line_length",
+        help = "Truncate long lines or skip the file as minified"
+    )]
+    pub long_lines: LongLineAction,
+
+    /// Fallback encoding for non-UTF-8 files
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Decode files that aren't valid UTF-8 with this encoding (e.g. shift_jis, latin1)"
+    )]
+    pub encoding: Option<String>,
+
+    /// Skip empty files
+    #[arg(long, help = "Skip files that are empty or contain only whitespace")]
+    pub skip_empty: bool,
+
+    /// Per-file token limit
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Truncate each file to roughly N tokens at a line boundary, with a marker"
+    )]
+    pub per_file_max_tokens: Option<usize>,
+
+    /// Policy for unreadable files
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = OnError::Warn,
+        help = "Abort on an unreadable file, skip it silently, or skip it with a warning"
+    )]
+    pub on_error: OnError,
+
+    /// Extensions counted with the full token estimator
+    #[arg(
+        long,
+        value_name = "EXTS",
+        value_parser = parse_extensions,
+        help = "Fully count tokens only for these extensions (e.g. \"rs,ts,py\"); estimate others from their size"
+    )]
+    pub count_ext: Option<Vec<String>>,
+
+    /// Model the token estimate is calibrated for
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODEL",
+        default_value_t = TokenModel::Generic,
+        help = "Calibrate the token estimate for this model's tokenizer"
+    )]
+    pub token_model: TokenModel,
+
+    /// Output order of the files
+    #[arg(
+        long,
+        value_enum,
+        value_name = "KEY",
+        help = "Order the files by path, size or tokens (largest first); within each group with --group-by-dir"
+    )]
+    pub sort: Option<SortKey>,
+
+    /// Extract the cells of Jupyter notebooks
+    #[cfg(feature = "notebook")]
+    #[arg(
+        long,
+        value_enum,
+        value_name = "CELLS",
+        num_args = 0..=1,
+        default_missing_value = "code",
+        help = "Emit the code cells (or all cells, with markdown as comments) of .ipynb files instead of their JSON"
+    )]
+    pub notebook_cells: Option<NotebookCells>,
+
+    /// Emit README files before the rest of their directory
+    #[arg(
+        long,
+        help = "Emit each directory's README* file before the other files in that directory"
+    )]
+    pub readme_first: bool,
+
+    /// Replace repeated file contents with references
+    #[arg(
+        long,
+        help = "Emit files identical to an earlier file as a reference to it (experimental)"
+    )]
+    pub dedup_blocks: bool,
+
+    /// Cap on the size of the output
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Stop adding content once the output reaches SIZE (e.g. 500k, 10m), with a notice"
+    )]
+    pub max_output_bytes: Option<usize>,
+
+    /// Embed small binary files as base64
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = parse_size,
+        help = "Emit binary files smaller than BYTES (e.g. 4k) as base64 with their MIME type instead of skipping them"
+    )]
+    pub embed_binary_under: Option<usize>,
+
+    /// Combine small files into one block
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = parse_size,
+        help = "Put files smaller than BYTES (e.g. 512, 2k) into a single shared code block"
+    )]
+    pub combine_under: Option<usize>,
+
+    /// Combine small files per directory
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = parse_size,
+        conflicts_with = "combine_under",
+        help = "Put the files smaller than BYTES in each directory into one code block headed by the directory"
+    )]
+    pub combine_per_dir_under: Option<usize>,
+
+    /// Separator between file blocks
+    #[arg(
+        long,
+        value_name = "TEXT",
+        value_parser = parse_escapes,
+        help = "Text inserted between file blocks, supporting \\n and \\t escapes (default: a blank line)"
+    )]
+    pub separator: Option<String>,
+}
+
+impl Cli {
+    /// Create a builder configured from the command-line options
+    pub fn builder(&self, current_dir: &Path) -> CflBuilder {
+        let mut builder = CflBuilder::new()
+            .include_patterns(self.include.join(","))
+            .exclude_patterns(self.exclude.join(","))
+            .include_names(self.include_names.as_deref().unwrap_or_default())
+            .apply_default_excludes(self.sensible_defaults)
+            .current_dir(current_dir)
+            .group_by_dir(self.group_by_dir)
+            .editorconfig(self.editorconfig)
+            .keep_line_endings(self.keep_line_endings)
+            .collapse_imports(self.collapse_imports)
+            .no_paths(self.no_paths)
+            .path_comment(self.path_comment)
+            .fail_on_binary(self.fail_on_binary)
+            .resolve_symlinks_in_paths(self.resolve_symlinks_in_paths)
+            .respect_binary_gitattributes(self.respect_binary_gitattributes)
+            .show_empty_dirs(self.show_empty_dirs)
+            .dirs_first(self.dirs_first)
+            .unsorted_walk(self.unsorted)
+            .preserve_arg_order(self.preserve_arg_order)
+            .skip_empty(self.skip_empty)
+            .fallback_encoding(self.encoding.clone())
+            .path_rewrites(self.rename.clone())
+            .project_relative(self.project_relative)
+            .dedup_blocks(self.dedup_blocks)
+            .readme_first(self.readme_first)
+            .use_dockerignore(self.dockerignore)
+            .repo_relative(self.repo_relative)
+            .output_format(self.output_format())
+            .hash(self.hash)
+            .annotate(self.annotate)
+            .footer_stats(self.footer_stats)
+            .git_info(
+                self.with_git_info
+                    .then(|| GitInfo::detect(current_dir))
+                    .flatten(),
+            )
+            .embed_command(
+                self.embed_command
+                    .then(|| self.command_line(current_dir, home_dir().as_deref())),
+            )
+            .long_line_action(self.long_lines)
+            .on_error(self.on_error);
+        if let Some(max_size) = self.max_size {
+            builder = builder.max_size(max_size);
+        }
+        if let Some(window) = self.modified_within {
+            builder = builder.modified_within(window);
+        }
+        if let Some((_, template)) = &self.template_file {
+            builder = builder.block_template(template.as_str());
+        }
+        if let Some(price) = self.price_per_1k_tokens {
+            builder = builder.price_per_1k_tokens(price);
+        }
+        if let Some(limits) = self.max_size_for.clone() {
+            builder = builder.max_size_per_ext(limits);
+        }
+        if let Some(limits) = self.max_tokens_per_lang.clone() {
+            builder = builder.max_tokens_per_lang(limits);
+        }
+        if let Some(max_line_length) = self.max_line_length {
+            builder = builder.max_line_length(max_line_length);
+        }
+        if let Some(max_tokens) = self.per_file_max_tokens {
+            builder = builder.per_file_max_tokens(max_tokens);
+        }
+        if let Some(bytes) = self.combine_under {
+            builder = builder.combine_under(bytes);
+        }
+        if let Some(bytes) = self.combine_per_dir_under {
+            builder = builder.combine_per_dir_under(bytes);
+        }
+        if let Some(bytes) = self.embed_binary_under {
+            builder = builder.embed_binary_under(bytes);
+        }
+        if let Some(key) = self.sort {
+            builder = builder.sort(key);
+        }
+        if let Some(extensions) = self.count_ext.clone() {
+            builder = builder.count_extensions(extensions);
+        }
+        builder = builder.token_model(self.token_model);
+        builder = builder.collect_content(!(self.summary_only || self.list_languages));
+        if let Some(bytes) = self.max_output_bytes {
+            builder = builder.max_output_bytes(bytes);
+        }
+        if let Some(separator) = &self.separator {
+            builder = builder.block_separator(separator.as_str());
+        }
+        #[cfg(feature = "notebook")]
+        if let Some(cells) = self.notebook_cells {
+            builder = builder.notebook_cells(cells);
+        }
+        #[cfg(feature = "sniff")]
+        {
+            builder = builder.text_only(self.text_only);
+        }
+        #[cfg(feature = "git")]
+        {
+            builder = builder.include_tracked_ignored(self.include_gitignored_but_tracked);
+        }
+        #[cfg(feature = "git")]
+        if self.staged {
+            builder = builder.only_files(crate::git::staged_files(current_dir));
+        }
+        // 監視中は前回の出力を読み込まないようにする
+        #[cfg(feature = "watch")]
+        if self.watch {
+            builder = builder.skip_paths(self.written_paths(current_dir));
+        }
+        builder
+    }
+
+    /// Canonical paths of the files and directories a run writes (`--output`,
+    /// `--stats-json` and `--output-dir`), resolved against `current_dir`
+    pub fn written_paths(&self, current_dir: &Path) -> Vec<PathBuf> {
+        [&self.output, &self.stats_json, &self.output_dir]
+            .into_iter()
+            .flatten()
+            .map(|path| canonicalize_lenient(&current_dir.join(path)))
+            .collect()
+    }
+
+    /// Whether the terminal summary is colorized: only when stdout `is_terminal`, unless
+    /// `--no-color` is given or `NO_COLOR` is set to a non-empty value (looked up through `env`)
+    pub fn color_enabled(&self, is_terminal: bool, env: impl Fn(&str) -> Option<String>) -> bool {
+        is_terminal && !self.no_color && env("NO_COLOR").is_none_or(|value| value.is_empty())
+    }
+
+    /// Whether the clipboard is turned off by `--no-clipboard` or by `CFL_NO_CLIPBOARD`
+    /// (any value except empty or `0`), looked up through `env`
+    pub fn clipboard_disabled(&self, env: impl Fn(&str) -> Option<String>) -> bool {
+        self.no_clipboard
+            || env("CFL_NO_CLIPBOARD").is_some_and(|value| !value.is_empty() && value != "0")
+    }
+
+    /// Fail early if the result would go to the clipboard while it is disabled
+    pub fn check_clipboard(&self, env: impl Fn(&str) -> Option<String>) -> Result<(), CflError> {
+        let uses_clipboard = !(self.count_tokens_only
+            || self.file_list
+            || self.cost_tree
+            || self.summary_only
+            || self.list_languages
+            || self.show
+            || self.show_json
+            || self.output_dir.is_some())
+            && self.sinks().contains(&Sink::Clipboard);
+        if uses_clipboard && self.clipboard_disabled(env) {
+            return Err(CflError::Clipboard(
+                "the clipboard is disabled by --no-clipboard or CFL_NO_CLIPBOARD; \
+                 use --stdout or --output <FILE>"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Where the result is written: the `--output` file and stdout as requested, and the
+    /// clipboard when neither is requested or with `--clipboard`
+    pub fn sinks(&self) -> Vec<Sink> {
+        let mut sinks = Vec::new();
+        if let Some(output) = &self.output {
+            sinks.push(Sink::File(output.clone()));
+        }
+        if self.clipboard || !(self.stdout || self.output.is_some()) {
+            sinks.push(Sink::Clipboard);
+        }
+        if self.stdout {
+            sinks.push(Sink::Stdout);
+        }
+        sinks
+    }
+
+    /// The paths given as `PATHS`, split at commas (none with only `--from-glob`)
+    pub fn paths(&self) -> Vec<&str> {
+        self.paths
+            .as_deref()
+            .map(|paths| paths.split(',').collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether `PATHS` is `-`, so the paths are read from stdin
+    pub fn reads_paths_from_stdin(&self) -> bool {
+        self.paths.as_deref() == Some("-")
+    }
+
+    /// Refuse to walk the filesystem root or `home` unless `--force` is given, whether it is
+    /// given in `PATHS` or is `current_dir` walked by `--from-glob`
+    pub fn check_root_guard(
+        &self,
+        current_dir: &Path,
+        home: Option<&Path>,
+    ) -> Result<(), CflError> {
+        if self.force {
+            return Ok(());
+        }
+
+        let home = home.and_then(|home| home.canonicalize().ok());
+        // --from-glob はカレントディレクトリを走査する
+        let glob_base = (!self.from_glob.is_empty()).then_some(".");
+        for path in self.paths().into_iter().chain(glob_base) {
+            let Ok(resolved) = current_dir.join(path).canonicalize() else {
+                continue;
+            };
+            if resolved.parent().is_none() || Some(&resolved) == home.as_ref() {
+                return Err(CflError::UnconfirmedRoot(resolved.display().to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a preflight `estimate` against `--max-total-files-warning`
+    ///
+    /// # Returns
+    ///
+    /// A warning to print when the threshold is exceeded, or an error with
+    /// `--preflight-strict` unless `--force` is given
+    pub fn check_preflight(&self, estimate: &Estimate) -> Result<Option<String>, CflError> {
+        let Some(threshold) = self.max_total_files_warning else {
+            return Ok(None);
+        };
+        if estimate.files <= threshold {
+            return Ok(None);
+        }
+        if self.preflight_strict && !self.force {
+            return Err(CflError::TooManyFiles(estimate.files, threshold));
+        }
+
+        Ok(Some(format!(
+            "About {} files ({} bytes, ~{} tokens) will be processed, more than {}",
+            format_number(estimate.files),
+            format_number(estimate.total_size as usize),
+            format_number((estimate.total_size as usize).div_ceil(4)),
+            format_number(threshold)
+        )))
+    }
+
+    /// The cfl invocation reproducing this output, for `--embed-command`
+    ///
+    /// Only options that change the copied content are listed. Paths below `current_dir`
+    /// are shown relative to it and paths below `home` start with `~`, so the command
+    /// doesn't reveal where the project lives.
+    pub fn command_line(&self, current_dir: &Path, home: Option<&Path>) -> String {
+        let paths = self
+            .paths()
+            .into_iter()
+            .map(|path| relativize(Path::new(path), current_dir, home))
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut args = vec!["cfl".to_string()];
+        if self.paths.is_some() {
+            args.push(shell_quote(&paths));
+        }
+
+        let mut push = |flag: &str, value: Option<String>| {
+            args.push(flag.to_string());
+            args.extend(value.map(|value| shell_quote(&value)));
+        };
+        for glob in &self.from_glob {
+            push("--from-glob", Some(glob.clone()));
+        }
+        #[cfg(feature = "git")]
+        if let Some(rev) = &self.git_ref {
+            push("--ref", Some(rev.clone()));
+        }
+        for include in &self.include {
+            push("-i", Some(include.clone()));
+        }
+        for exclude in &self.exclude {
+            push("-e", Some(exclude.clone()));
+        }
+        if let Some(names) = &self.include_names {
+            push("--include-names", Some(names.clone()));
+        }
+        if let Some(key) = self.sort.and_then(|key| key.to_possible_value()) {
+            push("--sort", Some(key.get_name().to_string()));
+        }
+        #[cfg(feature = "notebook")]
+        if let Some(cells) = self
+            .notebook_cells
+            .and_then(|cells| cells.to_possible_value())
+        {
+            push("--notebook-cells", Some(cells.get_name().to_string()));
+        }
+        if self.token_model != TokenModel::default() {
+            let model = self.token_model.to_possible_value();
+            push(
+                "--token-model",
+                model.map(|value| value.get_name().to_string()),
+            );
+        }
+        if self.output_format() != OutputFormat::default() {
+            let format = self.output_format().to_possible_value();
+            push("--format", format.map(|value| value.get_name().to_string()));
+        }
+        for (from, to) in &self.rename {
+            push("--rename", Some(format!("{}={}", from, to)));
+        }
+
+        let values = [
+            ("--max-size", self.max_size),
+            ("--max-line-length", self.max_line_length),
+            ("--per-file-max-tokens", self.per_file_max_tokens),
+            ("--combine-under", self.combine_under),
+            ("--combine-per-dir-under", self.combine_per_dir_under),
+            ("--embed-binary-under", self.embed_binary_under),
+            ("--max-output-bytes", self.max_output_bytes),
+        ];
+        for (flag, value) in values {
+            if let Some(value) = value {
+                push(flag, Some(value.to_string()));
+            }
+        }
+        if let Some(limits) = &self.max_tokens_per_lang {
+            let mut limits: Vec<String> = limits
+                .iter()
+                .map(|(language, tokens)| format!("{}={}", language, tokens))
+                .collect();
+            limits.sort();
+            push("--max-tokens-per-lang", Some(limits.join(",")));
+        }
+        if let Some(limits) = &self.max_size_for {
+            let mut limits: Vec<String> = limits
+                .iter()
+                .map(|(ext, bytes)| format!("{}={}", ext, bytes))
+                .collect();
+            limits.sort();
+            push("--max-size-for", Some(limits.join(",")));
+        }
+        if self.long_lines != LongLineAction::default() {
+            let action = self.long_lines.to_possible_value();
+            push(
+                "--long-lines",
+                action.map(|value| value.get_name().to_string()),
+            );
+        }
+        if let Some(separator) = &self.separator {
+            push("--separator", Some(escape(separator)));
+        }
+        if let Some(encoding) = &self.encoding {
+            push("--encoding", Some(encoding.clone()));
+        }
+        if let Some((path, _)) = &self.template_file {
+            push("--template-file", Some(path.display().to_string()));
+        }
+        if let Some(price) = self.price_per_1k_tokens {
+            push("--price-per-1k-tokens", Some(price.to_string()));
+        }
+        if let Some(window) = self.modified_within {
+            push("--modified-within", Some(format!("{}s", window.as_secs())));
+        }
+
+        let flags = [
+            ("--sensible-defaults", self.sensible_defaults),
+            ("--with-git-info", self.with_git_info),
+            ("--group-by-dir", self.group_by_dir),
+            ("--editorconfig", self.editorconfig),
+            ("--keep-line-endings", self.keep_line_endings),
+            ("--collapse-imports", self.collapse_imports),
+            ("--dockerignore", self.dockerignore),
+            ("--unsorted", self.unsorted),
+            (
+                "--respect-binary-gitattributes",
+                self.respect_binary_gitattributes,
+            ),
+            #[cfg(feature = "git")]
+            (
+                "--include-gitignored-but-tracked",
+                self.include_gitignored_but_tracked,
+            ),
+            ("--preserve-arg-order", self.preserve_arg_order),
+            ("--no-paths", self.no_paths),
+            ("--path-comment", self.path_comment),
+            (
+                "--resolve-symlinks-in-paths",
+                self.resolve_symlinks_in_paths,
+            ),
+            ("--hash", self.hash),
+            ("--annotate", self.annotate),
+            ("--footer-stats", self.footer_stats),
+            ("--project-relative", self.project_relative),
+            ("--repo-relative", self.repo_relative),
+            ("--skip-empty", self.skip_empty),
+            ("--dedup-blocks", self.dedup_blocks),
+            #[cfg(feature = "git")]
+            ("--staged", self.staged),
+            ("--readme-first", self.readme_first),
+            ("--fail-on-binary", self.fail_on_binary),
+            #[cfg(feature = "sniff")]
+            ("--text-only", self.text_only),
+        ];
+        for (flag, enabled) in flags {
+            if enabled {
+                push(flag, None);
+            }
+        }
+
+        args.join(" ")
+    }
+
+    /// The output format selected by `--format` or `--raw`
+    pub fn output_format(&self) -> OutputFormat {
+        if self.raw {
+            OutputFormat::Raw
+        } else {
+            self.format
+        }
+    }
+}
+
+/// Split paths read from stdin at newlines, or at NUL bytes with `null`, skipping empty entries
+pub fn split_path_list(input: &[u8], null: bool) -> Vec<PathBuf> {
+    let separator = if null { b'\0' } else { b'\n' };
+    input
+        .split(|&byte| byte == separator)
+        .map(|entry| {
+            if null {
+                entry
+            } else {
+                entry.strip_suffix(b"\r").unwrap_or(entry)
+            }
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| PathBuf::from(String::from_utf8_lossy(entry).into_owned()))
+        .collect()
+}
+
+/// The file list of the copy summary, one `  • path (size, tokens)` line per file, with
+/// numbers grouped by `thousands_sep`. With `limit`, only the first `limit` files are
+/// listed, followed by an `  ... and N more` line.
+pub fn file_listing(files: &[FileInfo], limit: Option<usize>, thousands_sep: char) -> String {
+    let number = |num: usize| format_number_with(num, thousands_sep);
+    let shown = limit.unwrap_or(files.len()).min(files.len());
+    let mut listing = String::new();
+    for file in &files[..shown] {
+        listing.push_str(&format!(
+            "  • {} ({} bytes, {} tokens)\n",
+            file.path,
+            number(file.size),
+            number(file.tokens)
+        ));
+    }
+    if shown < files.len() {
+        listing.push_str(&format!("  ... and {} more\n", number(files.len() - shown)));
+    }
+    listing
+}
+
+/// Output of `--count-tokens-only`: the bare total token count, without separators
+pub fn count_tokens_output(processor: &FileProcessor) -> String {
+    processor.get_total_tokens().to_string()
+}
+
+/// Output of `--summary-only`: the file count, size, tokens and per-language file counts,
+/// with numbers grouped by `thousands_sep`
+pub fn summary_output(stats: &Stats, thousands_sep: char) -> String {
+    let number = |num: usize| format_number_with(num, thousands_sep);
+    let mut output = format!(
+        "📂 Files: {}\n📦 Size: {} bytes\n🔤 Tokens: {}\n",
+        number(stats.files),
+        number(stats.total_size),
+        number(stats.total_tokens)
+    );
+    if !stats.languages.is_empty() {
+        let languages: Vec<String> = stats
+            .languages
+            .iter()
+            .map(|(language, files)| format!("{} ({})", language, number(*files)))
+            .collect();
+        output.push_str(&format!("🗂️  Languages: {}\n", languages.join(", ")));
+    }
+    output
+}
+
+/// Output of `--list-languages`: one `language: files` line per detected language, most
+/// files first, then an `(other)` line for files of no known language
+pub fn languages_output(stats: &Stats, thousands_sep: char) -> String {
+    let mut languages: Vec<(&String, &usize)> = stats.languages.iter().collect();
+    languages.sort_by(|(a_name, a_files), (b_name, b_files)| {
+        b_files.cmp(a_files).then(a_name.cmp(b_name))
+    });
+
+    let mut output = String::new();
+    for (language, files) in languages {
+        output.push_str(&format!(
+            "{}: {}\n",
+            language,
+            format_number_with(*files, thousands_sep)
+        ));
+    }
+    let other = stats.files - stats.languages.values().sum::<usize>();
+    if other > 0 {
+        output.push_str(&format!(
+            "(other): {}\n",
+            format_number_with(other, thousands_sep)
+        ));
+    }
+    output
+}
+
+/// Parse a size such as `512`, `50k` or `2m` (binary units) into bytes
+pub fn parse_size(value: &str) -> Result<usize, String> {
+    let lower = value.trim().to_ascii_lowercase();
+    let digits = lower.trim_end_matches('b');
+    let (number, multiplier) = match digits.char_indices().last() {
+        Some((index, 'k')) => (&digits[..index], 1024),
+        Some((index, 'm')) => (&digits[..index], 1024 * 1024),
+        Some((index, 'g')) => (&digits[..index], 1024 * 1024 * 1024),
+        _ => (digits, 1),
+    };
+
+    let number = number
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| format!("invalid size: {}", value))?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size too large: {}", value))
+}
+
+/// Parse a duration such as `90s`, `30m`, `24h`, `7d` or `2w` (plain numbers are seconds)
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let lower = value.trim().to_ascii_lowercase();
+    let (number, seconds) = match lower.char_indices().last() {
+        Some((index, 's')) => (&lower[..index], 1),
+        Some((index, 'm')) => (&lower[..index], 60),
+        Some((index, 'h')) => (&lower[..index], 60 * 60),
+        Some((index, 'd')) => (&lower[..index], 24 * 60 * 60),
+        Some((index, 'w')) => (&lower[..index], 7 * 24 * 60 * 60),
+        _ => (lower.as_str(), 1),
+    };
+
+    let number = number
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| format!("invalid duration: {}", value))?;
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration too long: {}", value))
+}
+
+/// Parse comma-separated `ext=size` pairs such as `json=50k,lock=0`
+pub fn parse_size_limits(value: &str) -> Result<HashMap<String, usize>, String> {
+    value
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (ext, size) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected EXT=SIZE, got: {}", pair))?;
+            let ext = ext.trim().trim_start_matches('.').to_string();
+            Ok((ext, parse_size(size)?))
+        })
+        .collect()
+}
+
+/// Parse per-language token limits such as `typescript=5000,json=1000`
+pub fn parse_token_limits(value: &str) -> Result<HashMap<String, usize>, String> {
+    value
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (language, tokens) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected LANG=N, got: {}", pair))?;
+            let tokens = tokens
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("invalid token count: {}", tokens))?;
+            Ok((language.trim().to_ascii_lowercase(), tokens))
+        })
+        .collect()
+}
+
+/// Parse comma-separated file extensions, with or without the leading dot
+pub fn parse_extensions(value: &str) -> Result<Vec<String>, String> {
+    Ok(value
+        .split(',')
+        .map(|ext| ext.trim().trim_start_matches('.'))
+        .filter(|ext| !ext.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Parse a `from=to` path rename
+pub fn parse_rename(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+            Ok((from.to_string(), to.to_string()))
+        }
+        _ => Err(format!("expected FROM=TO, got: {}", value)),
+    }
+}
+
+/// Unescape `\n`, `\t` and `\\` in a command-line value
+pub fn parse_escapes(value: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => return Err(format!("unsupported escape: \\{}", other)),
+            None => unescaped.push('\\'),
+        }
+    }
+    Ok(unescaped)
+}
+
+/// Inverse of [`parse_escapes`]: write backslashes, newlines and tabs as escapes
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+/// Read a block template from a file, checking its placeholders
+pub fn parse_template_file(path: &str) -> Result<(PathBuf, String), String> {
+    let template = std::fs::read_to_string(path)
+        .map_err(|err| format!("can't read template {}: {}", path, err))?;
+    crate::format::validate_template(&template).map_err(|err| err.to_string())?;
+    Ok((PathBuf::from(path), template))
+}
+
+/// The current user's home directory, from `HOME` (`USERPROFILE` on Windows)
+pub fn home_dir() -> Option<PathBuf> {
+    std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" }).map(PathBuf::from)
+}
+
+/// Show `path` relative to `current_dir`, or below `~` when it is inside `home`
+fn relativize(path: &Path, current_dir: &Path, home: Option<&Path>) -> String {
+    if !path.is_absolute() {
+        return path.display().to_string();
+    }
+    if let Ok(relative) = path.strip_prefix(current_dir) {
+        if relative.as_os_str().is_empty() {
+            return ".".to_string();
+        }
+        return relative.display().to_string();
+    }
+    match home.and_then(|home| path.strip_prefix(home).ok()) {
+        Some(relative) => Path::new("~").join(relative).display().to_string(),
+        None => path.display().to_string(),
+    }
+}
+
+/// Quote a command-line argument for POSIX shells when it contains special characters
+fn shell_quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./,=:~+@%".contains(c));
+    if plain {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
 }
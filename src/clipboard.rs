@@ -0,0 +1,157 @@
+use crate::error::CflError;
+use clipboard::{ClipboardContext, ClipboardProvider};
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+/// Clipboard mechanism used to copy the result
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClipboardBackend {
+    /// `wl-copy` from wl-clipboard, used on Wayland sessions
+    Wayland,
+    /// The X11 clipboard (also used by XWayland)
+    X11,
+    /// The native clipboard of macOS or Windows
+    System,
+}
+
+/// A clipboard accepting plain text and HTML flavors
+pub trait Clipboard {
+    /// Put plain text on the clipboard
+    fn set_text(&mut self, text: &str) -> Result<(), CflError>;
+
+    /// Put HTML on the clipboard, with `alt_text` as the plain-text flavor
+    fn set_html(&mut self, html: &str, alt_text: &str) -> Result<(), CflError>;
+}
+
+/// The clipboard of the current session, as picked by [`copy_to_clipboard`]
+///
+/// Only plain text is supported; use `HtmlClipboard` for the HTML flavor.
+#[derive(Debug, Default)]
+pub struct SystemClipboard {
+    backend: Option<ClipboardBackend>,
+}
+
+impl SystemClipboard {
+    /// The backend that received the last text, if any was copied
+    pub fn backend(&self) -> Option<ClipboardBackend> {
+        self.backend
+    }
+}
+
+impl Clipboard for SystemClipboard {
+    fn set_text(&mut self, text: &str) -> Result<(), CflError> {
+        self.backend = Some(copy_to_clipboard(text)?);
+        Ok(())
+    }
+
+    fn set_html(&mut self, _html: &str, _alt_text: &str) -> Result<(), CflError> {
+        Err(CflError::Clipboard(
+            "HTML needs the clipboard-html feature".to_string(),
+        ))
+    }
+}
+
+/// The system clipboard through `arboard`, which supports the HTML flavor
+#[cfg(feature = "clipboard-html")]
+pub struct HtmlClipboard(arboard::Clipboard);
+
+#[cfg(feature = "clipboard-html")]
+impl HtmlClipboard {
+    /// Connect to the system clipboard
+    pub fn new() -> Result<Self, CflError> {
+        arboard::Clipboard::new()
+            .map(Self)
+            .map_err(|e| CflError::Clipboard(e.to_string()))
+    }
+}
+
+#[cfg(feature = "clipboard-html")]
+impl Clipboard for HtmlClipboard {
+    fn set_text(&mut self, text: &str) -> Result<(), CflError> {
+        self.0
+            .set_text(text)
+            .map_err(|e| CflError::Clipboard(e.to_string()))
+    }
+
+    fn set_html(&mut self, html: &str, alt_text: &str) -> Result<(), CflError> {
+        self.0
+            .set_html(html, Some(alt_text))
+            .map_err(|e| CflError::Clipboard(e.to_string()))
+    }
+}
+
+/// Pick the clipboard backend for the session described by the environment lookup `env`.
+///
+/// On Wayland (`WAYLAND_DISPLAY` set) `wl-copy` is preferred, otherwise X11 is used when
+/// `DISPLAY` is set. Returns `None` on a headless Linux/BSD session.
+pub fn detect_backend(env: impl Fn(&str) -> Option<String>) -> Option<ClipboardBackend> {
+    if cfg!(any(target_os = "macos", target_os = "windows")) {
+        return Some(ClipboardBackend::System);
+    }
+
+    let is_set = |name: &str| env(name).is_some_and(|value| !value.is_empty());
+    if is_set("WAYLAND_DISPLAY") {
+        Some(ClipboardBackend::Wayland)
+    } else if is_set("DISPLAY") {
+        Some(ClipboardBackend::X11)
+    } else {
+        None
+    }
+}
+
+/// Copy `text` to the clipboard of the current session
+///
+/// Falls back from `wl-copy` to X11 when `wl-copy` isn't installed but XWayland is available.
+///
+/// # Returns
+///
+/// The backend that received the text
+pub fn copy_to_clipboard(text: &str) -> Result<ClipboardBackend, CflError> {
+    let env = |name: &str| std::env::var(name).ok();
+    match detect_backend(env) {
+        Some(ClipboardBackend::Wayland) => match wl_copy(text) {
+            Err(e) if e.kind() == ErrorKind::NotFound && env("DISPLAY").is_some() => {
+                log::debug!("wl-copy not found, falling back to the X11 clipboard");
+                x11_copy(text).map(|_| ClipboardBackend::X11)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(CflError::Clipboard(
+                "wl-copy not found; install wl-clipboard, or use --stdout or --output <FILE> instead"
+                    .to_string(),
+            )),
+            Err(e) => Err(CflError::Clipboard(format!("wl-copy failed: {}", e))),
+            Ok(()) => Ok(ClipboardBackend::Wayland),
+        },
+        Some(backend) => x11_copy(text).map(|_| backend),
+        None => Err(CflError::Clipboard(
+            "no clipboard available (neither WAYLAND_DISPLAY nor DISPLAY is set); \
+             use --stdout or --output <FILE> instead"
+                .to_string(),
+        )),
+    }
+}
+
+/// Pipe `text` into `wl-copy`
+fn wl_copy(text: &str) -> std::io::Result<()> {
+    let mut child = Command::new("wl-copy")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!("exited with {}", status)))
+    }
+}
+
+/// Copy `text` using the `clipboard` crate (X11, macOS or Windows)
+fn x11_copy(text: &str) -> Result<(), CflError> {
+    let mut ctx: ClipboardContext =
+        ClipboardProvider::new().map_err(|e| CflError::Clipboard(e.to_string()))?;
+    ctx.set_contents(text.to_string())
+        .map_err(|e| CflError::Clipboard(e.to_string()))
+}
@@ -0,0 +1,35 @@
+/// ANSI styling for the terminal summary, which is never applied to the copied content
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Colors {
+    enabled: bool,
+}
+
+impl Colors {
+    /// Create a palette that styles text only when `enabled`
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    /// Style a key number (bold cyan)
+    pub fn number(&self, text: &str) -> String {
+        self.paint("1;36", text)
+    }
+
+    /// Style a warning (yellow)
+    pub fn warning(&self, text: &str) -> String {
+        self.paint("33", text)
+    }
+
+    /// Style a success message (green)
+    pub fn success(&self, text: &str) -> String {
+        self.paint("32", text)
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use glob::Pattern;
+use std::fs;
+use std::path::Path;
+
+/// Normalization settings resolved from an `.editorconfig` file
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct EditorConfig {
+    pub(crate) insert_final_newline: Option<bool>,
+    pub(crate) trim_trailing_whitespace: Option<bool>,
+}
+
+impl EditorConfig {
+    /// Resolve the settings for `path` from the nearest `.editorconfig` in its ancestors
+    pub(crate) fn for_file(path: &Path) -> Self {
+        let file_name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name,
+            None => return Self::default(),
+        };
+
+        for dir in path.ancestors().skip(1) {
+            if let Ok(config) = fs::read_to_string(dir.join(".editorconfig")) {
+                let relative = path.strip_prefix(dir).unwrap_or(path);
+                return Self::parse(&config, file_name, &relative.to_string_lossy());
+            }
+        }
+
+        Self::default()
+    }
+
+    /// Parse `.editorconfig` content, collecting the properties of every section matching the file
+    fn parse(config: &str, file_name: &str, relative_path: &str) -> Self {
+        let mut settings = Self::default();
+        let mut in_matching_section = false;
+
+        for line in config.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                in_matching_section = section_matches(section, file_name, relative_path);
+                continue;
+            }
+
+            if !in_matching_section {
+                continue;
+            }
+
+            if let Some((key, value)) = line.split_once('=') {
+                let value = match value.trim().to_ascii_lowercase().as_str() {
+                    "true" => Some(true),
+                    "false" => Some(false),
+                    _ => None,
+                };
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "insert_final_newline" => settings.insert_final_newline = value,
+                    "trim_trailing_whitespace" => settings.trim_trailing_whitespace = value,
+                    _ => {}
+                }
+            }
+        }
+
+        settings
+    }
+
+    /// Apply the settings to file content
+    pub(crate) fn apply(&self, content: &str) -> String {
+        let mut normalized = if self.trim_trailing_whitespace == Some(true) {
+            let mut trimmed = content
+                .lines()
+                .map(str::trim_end)
+                .collect::<Vec<_>>()
+                .join("\n");
+            if content.ends_with('\n') {
+                trimmed.push('\n');
+            }
+            trimmed
+        } else {
+            content.to_string()
+        };
+
+        if self.insert_final_newline == Some(true) && !normalized.ends_with('\n') {
+            normalized.push('\n');
+        }
+
+        normalized
+    }
+}
+
+/// Check whether a section glob applies to the file.
+/// Globs without a `/` match the file name, others match the path relative to the config.
+fn section_matches(section: &str, file_name: &str, relative_path: &str) -> bool {
+    expand_braces(section).iter().any(|glob| {
+        let (glob, target) = if glob.contains('/') {
+            (glob.trim_start_matches('/'), relative_path)
+        } else {
+            (glob.as_str(), file_name)
+        };
+        Pattern::new(glob).is_ok_and(|pattern| pattern.matches(target))
+    })
+}
+
+/// Expand a single `{a,b}` alternation, which `glob` does not support
+fn expand_braces(section: &str) -> Vec<String> {
+    match (section.find('{'), section.find('}')) {
+        (Some(start), Some(end)) if start < end => section[start + 1..end]
+            .split(',')
+            .map(|alt| format!("{}{}{}", &section[..start], alt, &section[end + 1..]))
+            .collect(),
+        _ => vec![section.to_string()],
+    }
+}
@@ -15,6 +15,32 @@ pub enum CflError {
     #[error("Clipboard error: {0}")]
     Clipboard(String),
 
+    /// Unknown encoding label errors
+    #[error("Unknown encoding: {0}")]
+    UnknownEncoding(String),
+
+    /// Block template errors
+    #[error("Invalid template: {0}")]
+    InvalidTemplate(String),
+
+    /// Refusal to copy the filesystem root or home directory without confirmation
+    #[error("Refusing to copy {0}, which is the filesystem root or home directory; pass --force to proceed")]
+    UnconfirmedRoot(String),
+
+    /// Refusal to process more files than the preflight threshold without confirmation
+    #[error("About {0} files would be processed, more than the --max-total-files-warning threshold of {1}; pass --force to proceed")]
+    TooManyFiles(usize, usize),
+
+    /// A binary file found with `fail_on_binary`
+    #[error(
+        "Binary file found: {0}; adjust the include/exclude patterns or drop --fail-on-binary"
+    )]
+    BinaryFile(String),
+
+    /// Git revision errors
+    #[error("Can't read git revision {0}; is this a git repository with the git feature enabled?")]
+    GitRef(String),
+
     /// Path not found errors
     #[error("Path not found: {0}")]
     PathNotFound(String),
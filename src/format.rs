@@ -0,0 +1,850 @@
+use crate::error::CflError;
+use crate::git::GitInfo;
+use crate::language::{comment_syntax, detect_language};
+use crate::processor::{estimate_tokens, FileInfo, SkipReason, SkippedFile, Stats};
+use crate::transform;
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+/// Output format used for each file block
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Markdown code blocks headed by the file path
+    #[default]
+    Markdown,
+    /// Raw content preceded by a `// ==== path ====` banner, without code fences
+    Raw,
+    /// One JSON object per line and file, with its path, size, tokens and content
+    Jsonl,
+}
+
+impl OutputFormat {
+    /// Whether the output is meant for machines, so headings and footers are left out
+    pub(crate) fn is_structured(self) -> bool {
+        self == OutputFormat::Jsonl
+    }
+}
+
+/// Order of the files in the output
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// Alphabetically by path
+    Path,
+    /// Largest files first
+    Size,
+    /// Files with the most tokens first
+    Tokens,
+}
+
+/// Options controlling how a single file's content is formatted
+#[derive(Clone, Debug, Default)]
+pub struct FormatOptions {
+    /// Replace runs of import statements with a single placeholder line
+    pub collapse_imports: bool,
+    /// Output format of the block
+    pub format: OutputFormat,
+    /// Include a short SHA-256 content hash in the block header
+    pub hash: bool,
+    /// Precede each block with a comment giving the file's size and token count
+    pub annotate: bool,
+    /// Head each block with the file's language instead of its path, so no path appears
+    pub no_paths: bool,
+    /// Template each file's block is rendered with instead of the format's own block,
+    /// with `{path}`, `{content}` and `{lang}` placeholders (markdown and raw only)
+    pub template: Option<String>,
+    /// Start each block's content with a comment giving the file's path (e.g. `// src/main.rs`),
+    /// in the comment syntax of its language
+    pub path_comment: bool,
+    /// Emit the cells of `.ipynb` notebooks as plain source instead of their JSON
+    #[cfg(feature = "notebook")]
+    pub notebook_cells: Option<crate::notebook::NotebookCells>,
+}
+
+/// Options controlling how collected files are combined into the final output
+#[derive(Clone, Debug, Default)]
+pub struct RenderOptions {
+    /// Options applied to each file's block
+    pub format: FormatOptions,
+    /// Emit a `## dir/` heading before the files of each top-level directory
+    pub group_by_dir: bool,
+    /// Append a summary of the files at the end of the output
+    pub footer_stats: bool,
+    /// Price in dollars per 1,000 input tokens, adding an estimated cost to the footer
+    pub price_per_1k_tokens: Option<f64>,
+    /// Branch and commit named in a header at the start of the output
+    pub git_info: Option<GitInfo>,
+    /// Command line recorded in a block at the start of the output
+    pub command: Option<String>,
+    /// Combine files smaller than this many bytes into a single markdown block
+    pub combine_under: Option<usize>,
+    /// Combine files smaller than this many bytes into one markdown block per directory,
+    /// headed by the directory; takes precedence over `combine_under`
+    pub combine_per_dir_under: Option<usize>,
+    /// Text inserted between consecutive file blocks (`"\n"` when unset)
+    pub separator: Option<String>,
+    /// Emit files whose content is identical to an earlier file's as a reference to it
+    pub dedup_blocks: bool,
+    /// Prefix added to every non-empty output line, e.g. to nest blocks under a list item
+    /// (ignored for structured formats)
+    pub base_indent: String,
+    /// Order of the files, applied within each group with `group_by_dir`
+    /// (processing order when unset)
+    pub sort: Option<SortKey>,
+    /// Emit each directory's `README*` file before the other files in that directory
+    pub readme_first: bool,
+}
+
+impl RenderOptions {
+    fn separator(&self) -> &str {
+        self.separator.as_deref().unwrap_or("\n")
+    }
+}
+
+/// Render already collected files and their (transformed) contents as one output
+///
+/// This is what [`FileProcessor::get_result`](crate::FileProcessor::get_result) returns,
+/// without walking or reading anything, so files collected by other means can be
+/// rendered the same way.
+///
+/// # Examples
+///
+/// ```
+/// use cfl::{render, FileInfo, OutputFormat, RenderOptions};
+///
+/// let file = FileInfo { path: "a.rs".to_string(), size: 2, tokens: 1, hash: None, mime: None };
+/// let mut opts = RenderOptions::default();
+/// opts.format.format = OutputFormat::Raw;
+/// assert_eq!(render(&[(file, "a\n".to_string())], &opts), "// ==== a.rs ====\na\n\n");
+/// ```
+pub fn render(files: &[(FileInfo, String)], opts: &RenderOptions) -> String {
+    let files: Vec<(&FileInfo, &str)> = files
+        .iter()
+        .map(|(file, content)| (file, content.as_str()))
+        .collect();
+    render_sections(&[], &files, opts)
+}
+
+/// Render `leading` files as their own ungrouped section, followed by `files`
+pub(crate) fn render_sections(
+    leading: &[(&FileInfo, &str)],
+    files: &[(&FileInfo, &str)],
+    opts: &RenderOptions,
+) -> String {
+    if opts.sort.is_some() || opts.readme_first {
+        let mut ordered = files.to_vec();
+        if let Some(key) = opts.sort {
+            sort_files(&mut ordered, key);
+        }
+        if opts.readme_first {
+            move_readmes_first(&mut ordered);
+        }
+        let opts = RenderOptions {
+            sort: None,
+            readme_first: false,
+            ..opts.clone()
+        };
+        return render_sections(leading, &ordered, &opts);
+    }
+
+    if opts.dedup_blocks {
+        let all: Vec<(&FileInfo, &str)> = leading.iter().chain(files).copied().collect();
+        // 参照先のブロックが必ず先に出力されるよう、並べ替えとグループ化の後の順で判定する
+        let mut order: Vec<usize> = (0..all.len()).collect();
+        if opts.group_by_dir {
+            order[leading.len()..].sort_by_key(|&index| top_level_dir(&all[index].0.path));
+        }
+        let ordered: Vec<(&FileInfo, &str)> = order.iter().map(|&index| all[index]).collect();
+        let mut references = vec![None; all.len()];
+        for (&index, reference) in order.iter().zip(duplicate_references(&ordered)) {
+            references[index] = reference;
+        }
+        let deduped: Vec<(&FileInfo, &str)> = all
+            .iter()
+            .zip(&references)
+            .map(|(&(file, content), reference)| (file, reference.as_deref().unwrap_or(content)))
+            .collect();
+        let (leading, files) = deduped.split_at(leading.len());
+        let opts = RenderOptions {
+            dedup_blocks: false,
+            ..opts.clone()
+        };
+        return render_sections(leading, files, &opts);
+    }
+
+    let format = opts.format.format;
+    if format.is_structured() {
+        // JSON Linesでは1行1ファイルのみを出力する
+        return leading
+            .iter()
+            .chain(files)
+            .map(|(file, content)| format_block(file, content, &opts.format))
+            .collect();
+    }
+
+    let mut result = String::new();
+    if let Some(info) = &opts.git_info {
+        result.push_str(&git_header(info, format));
+    }
+    if let Some(command) = &opts.command {
+        result.push_str(&command_block(command, format));
+    }
+
+    let mut sections = Vec::new();
+    if !leading.is_empty() {
+        sections.push(join_blocks(leading, opts));
+    }
+    if !files.is_empty() {
+        sections.push(if opts.group_by_dir {
+            grouped_blocks(files, opts)
+        } else {
+            join_blocks(files, opts)
+        });
+    }
+    result.push_str(&sections.join(opts.separator()));
+
+    if opts.footer_stats {
+        let all: Vec<&FileInfo> = leading.iter().chain(files).map(|(file, _)| *file).collect();
+        result.push_str(&footer(&all, format, opts.price_per_1k_tokens));
+    }
+    if !opts.base_indent.is_empty() {
+        result = indent_lines(&result, &opts.base_indent);
+    }
+    result
+}
+
+/// Prefix every non-empty line with `indent`, keeping fences aligned with the content
+fn indent_lines(text: &str, indent: &str) -> String {
+    let mut indented = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        if line != "\n" {
+            indented.push_str(indent);
+        }
+        indented.push_str(line);
+    }
+    indented
+}
+
+/// Sort files by `key`; the sort is stable, so grouping afterwards keeps the order
+/// within each group
+fn sort_files(files: &mut [(&FileInfo, &str)], key: SortKey) {
+    match key {
+        SortKey::Path => files.sort_by(|(a, _), (b, _)| a.path.cmp(&b.path)),
+        SortKey::Size => files.sort_by_key(|(file, _)| std::cmp::Reverse(file.size)),
+        SortKey::Tokens => files.sort_by_key(|(file, _)| std::cmp::Reverse(file.tokens)),
+    }
+}
+
+/// Move each `README*` file right before the first file of its directory (including
+/// subdirectories), keeping the order of everything else
+fn move_readmes_first(files: &mut [(&FileInfo, &str)]) {
+    let paths: Vec<&Path> = files
+        .iter()
+        .map(|(file, _)| Path::new(&file.path))
+        .collect();
+    let keys: Vec<(usize, bool)> = paths
+        .iter()
+        .enumerate()
+        .map(
+            |(index, path)| match path.parent().filter(|_| is_readme(path)) {
+                Some(dir) => (
+                    paths
+                        .iter()
+                        .position(|p| p.starts_with(dir))
+                        .unwrap_or(index),
+                    false,
+                ),
+                None => (index, true),
+            },
+        )
+        .collect();
+
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by_key(|&index| keys[index]);
+    let reordered: Vec<(&FileInfo, &str)> = order.iter().map(|&index| files[index]).collect();
+    files.copy_from_slice(&reordered);
+}
+
+/// Whether the file name starts with `README`, in any case
+fn is_readme(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.to_ascii_lowercase().starts_with("readme"))
+}
+
+/// For each file whose content is identical to an earlier file's, a short reference
+/// to that first file to emit instead of the content
+fn duplicate_references(files: &[(&FileInfo, &str)]) -> Vec<Option<String>> {
+    let mut first_paths: HashMap<&str, &str> = HashMap::new();
+    files
+        .iter()
+        .map(|&(file, content)| match first_paths.get(content) {
+            Some(first) => Some(format!("// (identical content as {})\n", first)),
+            None => {
+                first_paths.insert(content, &file.path);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Format the given files under a `## dir/` heading per top-level directory
+fn grouped_blocks(files: &[(&FileInfo, &str)], opts: &RenderOptions) -> String {
+    // 先頭のパス要素ごとにファイルをまとめる
+    let mut groups: BTreeMap<String, Vec<(&FileInfo, &str)>> = BTreeMap::new();
+    for &(file, content) in files {
+        groups
+            .entry(top_level_dir(&file.path))
+            .or_default()
+            .push((file, content));
+    }
+
+    let mut result = String::new();
+    for (position, (dir, files)) in groups.iter().enumerate() {
+        if position > 0 {
+            result.push('\n');
+        }
+        result.push_str(&format!("## {}\n\n", dir));
+        result.push_str(&join_blocks(files, opts));
+    }
+    result
+}
+
+/// Format the given files' blocks, with the separator between them.
+/// With `combine_under`, small files share one block placed where the first of them was;
+/// with `combine_per_dir_under`, small files share one block per directory.
+fn join_blocks(files: &[(&FileInfo, &str)], opts: &RenderOptions) -> String {
+    // 結合先のグループ: 全体で1つ、またはディレクトリごと
+    let group_of = |file: &FileInfo| -> Option<String> {
+        if opts.format.format != OutputFormat::Markdown
+            || file.mime.is_some()
+            || opts.format.no_paths
+        {
+            return None;
+        }
+        match (opts.combine_per_dir_under, opts.combine_under) {
+            (Some(limit), _) => (file.size < limit).then(|| parent_dir(&file.path)),
+            (None, Some(limit)) => (file.size < limit).then(String::new),
+            (None, None) => None,
+        }
+    };
+
+    let mut groups: HashMap<String, Vec<(&FileInfo, &str)>> = HashMap::new();
+    for &(file, content) in files {
+        if let Some(group) = group_of(file) {
+            groups.entry(group).or_default().push((file, content));
+        }
+    }
+
+    let mut blocks = Vec::new();
+    for &(file, content) in files {
+        let combined = group_of(file)
+            .and_then(|group| groups.get_key_value(&group))
+            .filter(|(_, members)| members.len() >= 2);
+        match combined {
+            Some((group, members)) => {
+                if std::ptr::eq(members[0].0, file) {
+                    let dir = opts.combine_per_dir_under.map(|_| group.as_str());
+                    blocks.push(format_combined_block(members, dir));
+                }
+            }
+            None => blocks.push(format_block(file, content, &opts.format)),
+        }
+    }
+    blocks.join(opts.separator())
+}
+
+/// Directory of a relative path with a trailing `/` (`./` for the top level)
+fn parent_dir(relative_path: &str) -> String {
+    match relative_path.rsplit_once('/') {
+        Some((dir, _)) => format!("{}/", dir),
+        None => "./".to_string(),
+    }
+}
+
+/// Format the header naming the branch and commit of the output
+fn git_header(info: &GitInfo, format: OutputFormat) -> String {
+    let summary = format!("Generated from {}", info.describe());
+    match format {
+        OutputFormat::Markdown => format!("<!-- {} -->\n\n", summary),
+        OutputFormat::Raw => format!("// {}\n\n", summary),
+        OutputFormat::Jsonl => String::new(),
+    }
+}
+
+/// Format the block recording the command that produced the output
+fn command_block(command: &str, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Markdown => format!("```sh\n{}\n```\n\n", command),
+        OutputFormat::Raw => format!("// {}\n\n", command),
+        OutputFormat::Jsonl => String::new(),
+    }
+}
+
+/// Format the summary footer appended by `footer_stats`
+fn footer(files: &[&FileInfo], format: OutputFormat, price_per_1k_tokens: Option<f64>) -> String {
+    let languages: BTreeSet<&str> = files
+        .iter()
+        .filter_map(|file| detect_language(Path::new(&file.path)))
+        .collect();
+    let tokens = files.iter().map(|file| file.tokens).sum();
+    let mut summary = format!(
+        "Total: {} files, {} tokens, languages: {}",
+        format_number(files.len()),
+        format_number(tokens),
+        if languages.is_empty() {
+            "none".to_string()
+        } else {
+            languages.into_iter().collect::<Vec<_>>().join(", ")
+        }
+    );
+    if let Some(price) = price_per_1k_tokens {
+        summary.push_str(&format!(", estimated cost: {}", format_cost(tokens, price)));
+    }
+
+    match format {
+        OutputFormat::Markdown => format!("\n<!-- {} -->\n", summary),
+        OutputFormat::Raw => format!("// {}\n", summary),
+        OutputFormat::Jsonl => String::new(),
+    }
+}
+
+/// Estimated input cost of `tokens` at `price_per_1k_tokens` dollars per 1,000 tokens,
+/// e.g. `$0.0375`
+pub fn format_cost(tokens: usize, price_per_1k_tokens: f64) -> String {
+    format!("${:.4}", tokens as f64 / 1000.0 * price_per_1k_tokens)
+}
+
+/// Returns the top-level directory of a relative path (e.g. `src/` for `src/main.rs`),
+/// or `./` for files directly under the root
+fn top_level_dir(relative_path: &str) -> String {
+    let path = Path::new(relative_path);
+    let mut components = path.components();
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => format!("{}/", first.as_os_str().to_string_lossy()),
+        _ => "./".to_string(),
+    }
+}
+
+/// Format in-memory content as the markdown block `FileProcessor` would emit for it
+///
+/// Nothing is read from disk, so this also works for unsaved editor buffers.
+///
+/// # Arguments
+///
+/// * `relative_path` - The path shown in the block header
+/// * `content` - The file content
+/// * `opts` - Formatting options
+///
+/// # Examples
+///
+/// ```
+/// use cfl::{format_content, FormatOptions};
+///
+/// let block = format_content("src/main.rs", "fn main() {}", &FormatOptions::default());
+/// assert_eq!(block, "```src/main.rs\nfn main() {}\n```\n");
+/// ```
+pub fn format_content(relative_path: &str, content: &str, opts: &FormatOptions) -> String {
+    let content = transform_content(Path::new(relative_path), content, opts);
+    let file = file_info(relative_path, &content, estimate_tokens(&content), opts);
+    format_block(&file, &content, opts)
+}
+
+/// Collect the statistics of already transformed content with its token count
+pub(crate) fn file_info(
+    relative_path: &str,
+    content: &str,
+    tokens: usize,
+    opts: &FormatOptions,
+) -> FileInfo {
+    FileInfo {
+        path: relative_path.to_string(),
+        size: content.len(),
+        tokens,
+        hash: opts.hash.then(|| content_hash(content)),
+        mime: None,
+    }
+}
+
+/// First 8 hex digits of the SHA-256 digest of `content`
+fn content_hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .take(4)
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Apply the content transformations selected in `opts`
+pub(crate) fn transform_content(path: &Path, content: &str, opts: &FormatOptions) -> String {
+    #[cfg(feature = "notebook")]
+    if let Some(cells) = opts.notebook_cells {
+        let is_notebook = path.extension().is_some_and(|ext| ext == "ipynb");
+        if let Some(source) = is_notebook
+            .then(|| crate::notebook::extract_cells(content, cells))
+            .flatten()
+        {
+            return transform_content(
+                path,
+                &source,
+                &FormatOptions {
+                    notebook_cells: None,
+                    ..opts.clone()
+                },
+            );
+        }
+    }
+
+    if opts.collapse_imports {
+        transform::collapse_imports(path, content)
+    } else {
+        content.to_string()
+    }
+}
+
+/// Wrap already transformed content in a block headed by its path
+/// (by its language with `no_paths`). Trailing newlines are normalized first
+/// (see [`with_single_trailing_newline`]).
+pub(crate) fn format_block(file: &FileInfo, content: &str, opts: &FormatOptions) -> String {
+    let label = if opts.no_paths {
+        detect_language(Path::new(&file.path))
+            .unwrap_or_default()
+            .to_string()
+    } else {
+        file.path.clone()
+    };
+    let mut header = match &file.mime {
+        Some(mime) if label.is_empty() => format!("({}, base64)", mime),
+        Some(mime) => format!("{} ({}, base64)", label, mime),
+        None => label,
+    };
+    if let Some(hash) = &file.hash {
+        header = format!("{} sha256:{}", header, hash)
+            .trim_start()
+            .to_string();
+    }
+
+    let mut annotation = format!(
+        "{} bytes, {} tokens",
+        format_number(file.size),
+        format_number(file.tokens)
+    );
+    if !opts.no_paths {
+        annotation = format!("{}: {}", file.path, annotation);
+    }
+
+    let commented;
+    let content = match opts.format {
+        OutputFormat::Markdown | OutputFormat::Raw if opts.path_comment && file.mime.is_none() => {
+            commented = with_path_comment(&file.path, content);
+            commented.as_str()
+        }
+        _ => content,
+    };
+    let content = &with_single_trailing_newline(content);
+
+    match (&opts.template, opts.format) {
+        (Some(template), OutputFormat::Markdown | OutputFormat::Raw) if file.mime.is_none() => {
+            return apply_template(template, file, content);
+        }
+        _ => {}
+    }
+
+    match opts.format {
+        OutputFormat::Markdown => {
+            let fence = fence_for(content);
+            let annotation = if opts.annotate {
+                format!("<!-- {} -->\n", annotation)
+            } else {
+                String::new()
+            };
+            format!("{}{}{}\n{}{}\n", annotation, fence, header, content, fence)
+        }
+        OutputFormat::Raw => {
+            let annotation = if opts.annotate {
+                format!("// {}\n", annotation)
+            } else {
+                String::new()
+            };
+            if opts.no_paths {
+                return format!("{}{}\n", annotation, content);
+            }
+            format!("{}// ==== {} ====\n{}\n", annotation, header, content)
+        }
+        OutputFormat::Jsonl => {
+            let mut line = if opts.no_paths {
+                format!("{{\"size\":{},\"tokens\":{}", file.size, file.tokens)
+            } else {
+                format!(
+                    "{{\"path\":{},\"size\":{},\"tokens\":{}",
+                    json_string(&file.path),
+                    file.size,
+                    file.tokens
+                )
+            };
+            if let Some(hash) = &file.hash {
+                line.push_str(&format!(",\"hash\":{}", json_string(hash)));
+            }
+            if let Some(mime) = &file.mime {
+                line.push_str(&format!(",\"mime\":{}", json_string(mime)));
+            }
+            line.push_str(&format!(",\"content\":{}}}\n", json_string(content)));
+            line
+        }
+    }
+}
+
+/// The trailing-newline rule shared by every output format: non-empty content ends with
+/// exactly one line terminator, whatever the file ended with, and empty content stays empty.
+///
+/// The terminator is `\r\n` for content with CRLF line endings (kept with
+/// `keep_line_endings`), so the last line ends like the others, and `\n` otherwise.
+/// Renderers put their closing delimiter right after the content, so the same file always
+/// ends its block the same way in markdown, raw, JSON Lines and templates.
+pub(crate) fn with_single_trailing_newline(content: &str) -> String {
+    let trimmed = content.trim_end_matches(['\n', '\r']);
+    if trimmed.is_empty() {
+        return String::new();
+    }
+    let tail = &content[trimmed.len()..];
+    let crlf = tail.starts_with("\r\n") || (tail.is_empty() && trimmed.contains("\r\n"));
+    format!("{}{}", trimmed, if crlf { "\r\n" } else { "\n" })
+}
+
+/// Placeholders a block template may contain
+const TEMPLATE_PLACEHOLDERS: [&str; 3] = ["path", "content", "lang"];
+
+/// Check that a block template contains `{content}` and no unknown placeholder.
+///
+/// A placeholder is a lowercase name in braces; other braces are kept as text.
+pub fn validate_template(template: &str) -> Result<(), CflError> {
+    let mut has_content = false;
+    for name in template_placeholders(template) {
+        if !TEMPLATE_PLACEHOLDERS.contains(&name) {
+            return Err(CflError::InvalidTemplate(format!(
+                "unknown placeholder {{{}}}; use {{path}}, {{content}} or {{lang}}",
+                name
+            )));
+        }
+        has_content |= name == "content";
+    }
+    if !has_content {
+        return Err(CflError::InvalidTemplate(
+            "the template has no {content} placeholder".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Names of the `{name}` placeholders in `template`, in order
+fn template_placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template.split('{').skip(1).filter_map(|rest| {
+        let (name, _) = rest.split_once('}')?;
+        let is_name = !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c == '_');
+        is_name.then_some(name)
+    })
+}
+
+/// Render a file's block by filling in the placeholders of `template` in a single pass,
+/// so placeholders appearing in the content itself are left alone
+fn apply_template(template: &str, file: &FileInfo, content: &str) -> String {
+    let language = detect_language(Path::new(&file.path)).unwrap_or_default();
+    let mut block = String::with_capacity(template.len() + content.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        block.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.split_once('}').and_then(|(name, _)| match name {
+            "path" => Some((name, file.path.as_str())),
+            "content" => Some((name, content)),
+            "lang" => Some((name, language)),
+            _ => None,
+        });
+        match value {
+            Some((name, value)) => {
+                block.push_str(value);
+                rest = &after[name.len() + 1..];
+            }
+            None => {
+                block.push('{');
+                rest = after;
+            }
+        }
+    }
+    block.push_str(rest);
+    block
+}
+
+/// Insert a comment line with `path` at the start of `content`, after a `#!` line if there
+/// is one. Content in languages without comments or of unknown language is returned as is.
+fn with_path_comment(path: &str, content: &str) -> String {
+    let Some((start, end)) = detect_language(Path::new(path)).and_then(comment_syntax) else {
+        return content.to_string();
+    };
+    let comment = format!("{} {}{}\n", start, path, end);
+    match content.strip_prefix("#!").and(content.split_once('\n')) {
+        Some((shebang, rest)) => format!("{}\n{}{}", shebang, comment, rest),
+        None => format!("{}{}", comment, content),
+    }
+}
+
+/// Wrap several small files in a single markdown block, each preceded by a `// path` line.
+/// With `dir`, the block is headed by the directory and each file is named relative to it.
+pub(crate) fn format_combined_block(files: &[(&FileInfo, &str)], dir: Option<&str>) -> String {
+    let mut body = String::new();
+    for (file, content) in files {
+        let name = dir
+            .and_then(|dir| file.path.strip_prefix(dir))
+            .unwrap_or(&file.path);
+        body.push_str(&format!(
+            "// {}\n{}",
+            name,
+            with_single_trailing_newline(content)
+        ));
+    }
+
+    let fence = fence_for(&body);
+    format!("{}{}\n{}{}\n", fence, dir.unwrap_or_default(), body, fence)
+}
+
+/// Render a file as an HTML heading and `<pre>` block, tagged with its language
+/// (e.g. `language-rust`) for syntax highlighters
+pub(crate) fn html_block(file: &FileInfo, content: &str) -> String {
+    let class = detect_language(Path::new(&file.path))
+        .map(|language| format!(" class=\"language-{}\"", language))
+        .unwrap_or_default();
+    format!(
+        "<h3>{}</h3>\n<pre><code{}>{}</code></pre>\n",
+        html_escape(&file.path),
+        class,
+        html_escape(content)
+    )
+}
+
+/// Escape text for use in HTML
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Pick a fence longer than any backtick run at the start of a line in `content`,
+/// so embedded code blocks (e.g. in markdown files) can't terminate the block early
+fn fence_for(content: &str) -> String {
+    let longest = content
+        .lines()
+        .map(|line| line.trim_start().chars().take_while(|&c| c == '`').count())
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+/// Format a number with `,` as the thousands separator (e.g. `1,234,567`)
+pub fn format_number(num: usize) -> String {
+    format_number_with(num, ',')
+}
+
+/// Format a number with `separator` between groups of three digits
+/// (e.g. `1.234.567` with `.`)
+pub fn format_number_with(num: usize, separator: char) -> String {
+    let digits = num.to_string();
+    let mut formatted = String::with_capacity(digits.len() * 2);
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            formatted.push(separator);
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Render file metadata (path, size, tokens, language and hash if present) as a JSON
+/// array, without any file contents
+pub fn files_metadata_json(files: &[FileInfo]) -> String {
+    let entries = files
+        .iter()
+        .map(|file| {
+            let language =
+                detect_language(Path::new(&file.path)).map_or("null".to_string(), json_string);
+            let mut entry = format!(
+                "{{\"path\":{},\"size\":{},\"tokens\":{},\"language\":{}",
+                json_string(&file.path),
+                file.size,
+                file.tokens,
+                language
+            );
+            if let Some(hash) = &file.hash {
+                entry.push_str(&format!(",\"hash\":{}", json_string(hash)));
+            }
+            entry.push('}');
+            entry
+        })
+        .collect::<Vec<_>>();
+
+    format!("[{}]", entries.join(","))
+}
+
+/// Statistics of a run as a JSON object: the metadata of each file (as in
+/// [`files_metadata_json`]), the totals, the file count per language, the skipped files
+/// with their reason and the paths of the files that couldn't be read
+pub fn stats_json(stats: &Stats, files: &[FileInfo], skipped: &[SkippedFile]) -> String {
+    let languages = stats
+        .languages
+        .iter()
+        .map(|(language, count)| format!("{}:{}", json_string(language), count))
+        .collect::<Vec<_>>();
+    let skipped_entries = skipped
+        .iter()
+        .map(|file| {
+            format!(
+                "{{\"path\":{},\"reason\":{}}}",
+                json_string(&file.path),
+                json_string(file.reason.name())
+            )
+        })
+        .collect::<Vec<_>>();
+    let errors = skipped
+        .iter()
+        .filter(|file| file.reason == SkipReason::Unreadable)
+        .map(|file| json_string(&file.path))
+        .collect::<Vec<_>>();
+
+    format!(
+        "{{\"files\":{},\"total_files\":{},\"total_size\":{},\"total_tokens\":{},\"languages\":{{{}}},\"skipped\":[{}],\"errors\":[{}]}}",
+        files_metadata_json(files),
+        stats.files,
+        stats.total_size,
+        stats.total_tokens,
+        languages.join(","),
+        skipped_entries.join(","),
+        errors.join(",")
+    )
+}
+
+/// Encode a string as a JSON string literal
+pub(crate) fn json_string(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len() + 2);
+    encoded.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => encoded.push_str("\\\""),
+            '\\' => encoded.push_str("\\\\"),
+            '\n' => encoded.push_str("\\n"),
+            '\r' => encoded.push_str("\\r"),
+            '\t' => encoded.push_str("\\t"),
+            c if (c as u32) < 0x20 => encoded.push_str(&format!("\\u{:04x}", c as u32)),
+            c => encoded.push(c),
+        }
+    }
+    encoded.push('"');
+    encoded
+}
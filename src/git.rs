@@ -0,0 +1,164 @@
+use std::path::Path;
+#[cfg(feature = "git")]
+use std::path::PathBuf;
+
+/// Branch and commit a run was generated from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GitInfo {
+    /// Current branch, or `None` on a detached HEAD
+    pub branch: Option<String>,
+    /// Short commit hash
+    pub commit: String,
+}
+
+impl GitInfo {
+    /// Look up the branch and commit of the repository containing `dir`
+    ///
+    /// # Returns
+    ///
+    /// `None` outside a git repository, in a repository without commits, or when
+    /// the `git` feature is disabled
+    pub fn detect(dir: &Path) -> Option<Self> {
+        let commit = run_git(dir, &["rev-parse", "--short", "HEAD"])?;
+        let branch =
+            run_git(dir, &["rev-parse", "--abbrev-ref", "HEAD"]).filter(|branch| branch != "HEAD");
+        Some(Self { branch, commit })
+    }
+
+    /// `branch@commit`, or just the commit on a detached HEAD
+    pub(crate) fn describe(&self) -> String {
+        match &self.branch {
+            Some(branch) => format!("{}@{}", branch, self.commit),
+            None => self.commit.clone(),
+        }
+    }
+}
+
+/// Files under `dir` that git tracks, including force-added files matching an ignore rule.
+///
+/// Returns an empty list when `dir` isn't inside a git work tree or git isn't available.
+#[cfg(feature = "git")]
+pub(crate) fn tracked_files(dir: &Path) -> Vec<PathBuf> {
+    // ls-files はディレクトリからの相対パスを返す
+    run_git(dir, &["ls-files", "-z"])
+        .map(|output| {
+            output
+                .split('\0')
+                .filter(|path| !path.is_empty())
+                .map(|path| dir.join(path))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Files below `dir` with changes staged in the git index, relative to `dir`.
+/// Files whose deletion is staged are left out.
+///
+/// Returns an empty list when `dir` isn't inside a git work tree or git isn't available.
+#[cfg(feature = "git")]
+pub(crate) fn staged_files(dir: &Path) -> Vec<PathBuf> {
+    run_git(
+        dir,
+        &[
+            "diff",
+            "--cached",
+            "--name-only",
+            "-z",
+            "--relative",
+            "--diff-filter=d",
+        ],
+    )
+    .map(|output| {
+        output
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Files below `path` in the tree of the git revision `rev` (e.g. `HEAD~1`)
+///
+/// Each file is returned as `(path relative to dir, path in the repository, size in
+/// bytes)`; submodules have a size of 0. Returns `None` when `rev` can't be resolved, `dir`
+/// isn't in a git repository or git isn't installed.
+#[cfg(feature = "git")]
+pub(crate) fn tree_files(
+    dir: &Path,
+    rev: &str,
+    path: &Path,
+) -> Option<Vec<(PathBuf, String, u64)>> {
+    // ls-tree --full-name はリポジトリのルートからのパスを返す
+    let prefix = run_git(dir, &["rev-parse", "--show-prefix"]).unwrap_or_default();
+    let path = path.to_string_lossy();
+    let output = run_git(
+        dir,
+        &[
+            "ls-tree",
+            "-r",
+            "-z",
+            "--full-name",
+            "--long",
+            rev,
+            "--",
+            &path,
+        ],
+    )?;
+    Some(
+        output
+            .split('\0')
+            .filter_map(|entry| {
+                // "<mode> <type> <object> <size>\t<name>" の形式で、サイズは右寄せされている
+                let (info, name) = entry.split_once('\t')?;
+                let size = info.split_whitespace().nth(3)?.parse().unwrap_or(0);
+                let relative = name.strip_prefix(prefix.as_str()).unwrap_or(name);
+                Some((PathBuf::from(relative), name.to_string(), size))
+            })
+            .collect(),
+    )
+}
+
+/// Content of the file at `name` (relative to the repository root) in the revision `rev`
+#[cfg(feature = "git")]
+pub(crate) fn show_file(dir: &Path, rev: &str, name: &str) -> Option<Vec<u8>> {
+    run_git_bytes(dir, &["show", &format!("{}:{}", rev, name)])
+}
+
+/// Run a git command in `dir`, returning its stdout on success
+fn run_git(dir: &Path, args: &[&str]) -> Option<String> {
+    run_git_bytes(dir, args).map(|stdout| String::from_utf8_lossy(&stdout).trim_end().to_string())
+}
+
+/// Run a git command in `dir`, returning its raw stdout on success
+#[cfg(feature = "git")]
+fn run_git_bytes(dir: &Path, args: &[&str]) -> Option<Vec<u8>> {
+    let output = match std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) => {
+            log::debug!("Failed to run git: {}", err);
+            return None;
+        }
+    };
+
+    if !output.status.success() {
+        log::debug!(
+            "git {} failed in {}: {}",
+            args.join(" "),
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return None;
+    }
+    Some(output.stdout)
+}
+
+#[cfg(not(feature = "git"))]
+fn run_git_bytes(_dir: &Path, _args: &[&str]) -> Option<Vec<u8>> {
+    None
+}
@@ -0,0 +1,79 @@
+use std::path::Path;
+
+/// Infer the language of a file from its name or extension
+///
+/// # Returns
+///
+/// A lowercase language name such as `rust` or `python`, or `None` if unknown
+pub fn detect_language(path: &Path) -> Option<&'static str> {
+    let file_name = path.file_name()?.to_str()?;
+    let by_name = match file_name {
+        "Makefile" | "makefile" | "GNUmakefile" => Some("makefile"),
+        "Dockerfile" => Some("dockerfile"),
+        "CMakeLists.txt" => Some("cmake"),
+        _ => None,
+    };
+    if by_name.is_some() {
+        return by_name;
+    }
+
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let language = match extension.as_str() {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "scala" => "scala",
+        "sh" | "bash" | "zsh" => "shell",
+        "ps1" => "powershell",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "md" | "markdown" => "markdown",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "xml" => "xml",
+        "lua" => "lua",
+        "dart" => "dart",
+        "ex" | "exs" => "elixir",
+        "hs" => "haskell",
+        "ipynb" => "jupyter",
+        "txt" => "text",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Comment delimiters of a language as `(start, end)`, e.g. `("//", "")` or `("<!--", " -->")`
+///
+/// # Returns
+///
+/// `None` for languages without comments, such as JSON
+pub(crate) fn comment_syntax(language: &str) -> Option<(&'static str, &'static str)> {
+    let syntax = match language {
+        "rust" | "javascript" | "jsx" | "typescript" | "tsx" | "go" | "java" | "kotlin"
+        | "swift" | "c" | "cpp" | "csharp" | "php" | "scala" | "scss" | "dart" => ("//", ""),
+        "python" | "shell" | "ruby" | "powershell" | "yaml" | "toml" | "elixir" | "makefile"
+        | "dockerfile" | "cmake" => ("#", ""),
+        "sql" | "lua" | "haskell" => ("--", ""),
+        "css" => ("/*", " */"),
+        "html" | "xml" | "vue" | "svelte" | "markdown" => ("<!--", " -->"),
+        _ => return None,
+    };
+    Some(syntax)
+}
@@ -28,21 +28,119 @@
 //! }
 //! ```
 
+mod binary;
 pub mod cli;
+pub mod clipboard;
+pub mod color;
+mod editorconfig;
 pub mod error;
+pub mod format;
+mod git;
+pub mod language;
+#[cfg(feature = "notebook")]
+pub mod notebook;
 pub mod processor;
+pub mod sink;
+mod transform;
+pub mod watch;
 
 pub use anyhow::Result;
 pub use error::CflError;
-pub use processor::{FileInfo, FileProcessor};
+pub use format::{format_content, render, FormatOptions, OutputFormat, RenderOptions, SortKey};
+pub use git::GitInfo;
+pub use ignore::WalkBuilder;
+#[cfg(feature = "notebook")]
+pub use notebook::NotebookCells;
+pub use processor::{
+    Estimate, FileInfo, FileProcessor, IgnoreRule, Inclusion, LongLineAction, OnError, PathResult,
+    SkipReason, SkippedFile, Stats, TokenModel,
+};
 
+use processor::{ProcessorOptions, WalkConfigurator};
+
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The common settings of a [`CflBuilder`] as one value, for programmatic callers
+///
+/// # Examples
+///
+/// ```no_run
+/// use cfl::{CflBuilder, CflOptions, OutputFormat};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let options = CflOptions {
+///     include: Some("*.rs".to_string()),
+///     format: OutputFormat::Raw,
+///     ..CflOptions::default()
+/// };
+/// let processor = CflBuilder::from_options(options).build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CflOptions {
+    /// Include patterns (comma-separated)
+    pub include: Option<String>,
+    /// Exclude patterns (comma-separated)
+    pub exclude: Option<String>,
+    /// Skip files larger than this many bytes
+    pub max_size: Option<usize>,
+    /// Skip files hidden by `.gitignore` rules (on by default)
+    pub respect_gitignore: bool,
+    /// Also exclude common build and dependency directories
+    pub default_excludes: bool,
+    /// Output format of each file block
+    pub format: OutputFormat,
+    /// Group the output into sections per top-level directory
+    pub group_by_dir: bool,
+    /// Order of the files in the output (processing order when unset)
+    pub sort: Option<SortKey>,
+    /// Add a short content hash to each block header
+    pub hash: bool,
+    /// Precede each block with its size and token count
+    pub annotate: bool,
+    /// Append a summary of the files at the end of the output
+    pub footer_stats: bool,
+    /// Skip files that are empty or contain only whitespace
+    pub skip_empty: bool,
+    /// Truncate each file to roughly this many tokens
+    pub per_file_max_tokens: Option<usize>,
+    /// Stop adding content once the output reaches this many bytes
+    pub max_output_bytes: Option<usize>,
+}
+
+impl Default for CflOptions {
+    fn default() -> Self {
+        Self {
+            include: None,
+            exclude: None,
+            max_size: None,
+            respect_gitignore: true,
+            default_excludes: false,
+            format: OutputFormat::default(),
+            group_by_dir: false,
+            sort: None,
+            hash: false,
+            annotate: false,
+            footer_stats: false,
+            skip_empty: false,
+            per_file_max_tokens: None,
+            max_output_bytes: None,
+        }
+    }
+}
 
 /// Builder pattern for FileProcessor configuration
 pub struct CflBuilder {
     include_patterns: Option<String>,
     exclude_patterns: Option<String>,
+    default_excludes: bool,
     current_dir: PathBuf,
+    options: ProcessorOptions,
 }
 
 impl Default for CflBuilder {
@@ -56,8 +154,38 @@ impl CflBuilder {
         Self {
             include_patterns: None,
             exclude_patterns: None,
+            default_excludes: false,
             current_dir: std::env::current_dir().unwrap_or_default(),
+            options: ProcessorOptions::default(),
+        }
+    }
+
+    /// Create a builder configured from `options`; further builder methods can refine it
+    pub fn from_options(options: CflOptions) -> Self {
+        let mut builder = Self::new()
+            .include_patterns(options.include.unwrap_or_default())
+            .exclude_patterns(options.exclude.unwrap_or_default())
+            .respect_gitignore(options.respect_gitignore)
+            .apply_default_excludes(options.default_excludes)
+            .output_format(options.format)
+            .group_by_dir(options.group_by_dir)
+            .hash(options.hash)
+            .annotate(options.annotate)
+            .footer_stats(options.footer_stats)
+            .skip_empty(options.skip_empty);
+        if let Some(bytes) = options.max_size {
+            builder = builder.max_size(bytes);
+        }
+        if let Some(key) = options.sort {
+            builder = builder.sort(key);
         }
+        if let Some(tokens) = options.per_file_max_tokens {
+            builder = builder.per_file_max_tokens(tokens);
+        }
+        if let Some(bytes) = options.max_output_bytes {
+            builder = builder.max_output_bytes(bytes);
+        }
+        builder
     }
 
     pub fn include_patterns<S: Into<String>>(mut self, patterns: S) -> Self {
@@ -70,17 +198,498 @@ impl CflBuilder {
         self
     }
 
+    /// Skip files hidden by `.gitignore` rules, including the global gitignore and
+    /// `.git/info/exclude` (on by default). `.ignore` files apply either way.
+    pub fn respect_gitignore(mut self, enabled: bool) -> Self {
+        self.options.ignore_gitignore = !enabled;
+        self
+    }
+
+    /// Also exclude the common build, dependency and cache directories listed in
+    /// [`DEFAULT_EXCLUDED_DIRS`](processor::DEFAULT_EXCLUDED_DIRS) (`target`, `node_modules`,
+    /// `__pycache__`, ...), even when they aren't gitignored. As with other excludes, an
+    /// include pattern pointing inside one of them (e.g. `dist/app.js`) still matches.
+    pub fn apply_default_excludes(mut self, enabled: bool) -> Self {
+        self.default_excludes = enabled;
+        self
+    }
+
     pub fn current_dir<P: AsRef<Path>>(mut self, path: P) -> Self {
         self.current_dir = path.as_ref().to_path_buf();
         self
     }
 
+    /// Group the output into sections per top-level directory, each headed by `## dir/`
+    pub fn group_by_dir(mut self, enabled: bool) -> Self {
+        self.options.group_by_dir = enabled;
+        self
+    }
+
+    /// Normalize emitted content (final newline, trailing whitespace) per the nearest `.editorconfig`
+    pub fn editorconfig(mut self, enabled: bool) -> Self {
+        self.options.editorconfig = enabled;
+        self
+    }
+
+    /// Collapse runs of Rust `use`, Python `import`/`from` and JS `import` statements
+    /// into a single `// N imports collapsed` line
+    pub fn collapse_imports(mut self, enabled: bool) -> Self {
+        self.options.format.collapse_imports = enabled;
+        self
+    }
+
+    /// Leave file paths out of the output: markdown blocks are headed by the language
+    /// (e.g. ```` ```rust ````) and raw blocks have no banner
+    pub fn no_paths(mut self, enabled: bool) -> Self {
+        self.options.format.no_paths = enabled;
+        self
+    }
+
+    /// Render each file's block with `template` instead of the format's own block (markdown
+    /// and raw). `{path}`, `{content}` and `{lang}` are replaced by the file's path, content
+    /// and language; `build` fails if `{content}` is missing or another placeholder is used.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// let processor = cfl::CflBuilder::new()
+    ///     .block_template("### {path}\n```{lang}\n{content}\n```\n")
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn block_template<S: Into<String>>(mut self, template: S) -> Self {
+        self.options.format.template = Some(template.into());
+        self
+    }
+
+    /// Start each block's content with a comment giving the file's path, in the comment
+    /// syntax of its language (`// path` for Rust, `# path` for Python), so the path
+    /// survives when the markdown around the code is stripped
+    pub fn path_comment(mut self, enabled: bool) -> Self {
+        self.options.format.path_comment = enabled;
+        self
+    }
+
+    /// Fail with [`CflError::BinaryFile`] naming the first binary file found, instead of
+    /// skipping it. Binary files embedded with `embed_binary_under` don't count.
+    pub fn fail_on_binary(mut self, enabled: bool) -> Self {
+        self.options.fail_on_binary = enabled;
+        self
+    }
+
+    /// Treat files declared `binary` (or `-text`) in `.gitattributes` as binary and skip them,
+    /// and files declared `text` as text, regardless of what their content looks like
+    pub fn respect_binary_gitattributes(mut self, enabled: bool) -> Self {
+        self.options.respect_binary_gitattributes = enabled;
+        self
+    }
+
+    /// Include directories that contain no files in `get_directory_structure`
+    pub fn show_empty_dirs(mut self, enabled: bool) -> Self {
+        self.options.show_empty_dirs = enabled;
+        self
+    }
+
+    /// Set the output format of each file block
+    pub fn output_format(mut self, format: OutputFormat) -> Self {
+        self.options.format.format = format;
+        self
+    }
+
+    /// Annotate each file with a short SHA-256 hash of its content, in the block header
+    /// and in `FileInfo::hash`
+    pub fn hash(mut self, enabled: bool) -> Self {
+        self.options.format.hash = enabled;
+        self
+    }
+
+    /// Append a summary comment (file count, tokens, languages) at the end of the output
+    pub fn footer_stats(mut self, enabled: bool) -> Self {
+        self.options.footer_stats = enabled;
+        self
+    }
+
+    /// Add the estimated input cost at `dollars` per 1,000 tokens to the `footer_stats` summary
+    pub fn price_per_1k_tokens(mut self, dollars: f64) -> Self {
+        self.options.price_per_1k_tokens = Some(dollars);
+        self
+    }
+
+    /// Skip files larger than `bytes`
+    pub fn max_size(mut self, bytes: usize) -> Self {
+        self.options.max_size = Some(bytes);
+        self
+    }
+
+    /// Adjust the walker used to collect files (thread count, maximum depth, extra ignore
+    /// files, ...). `configure` runs on every walk after the default configuration, so its
+    /// settings win; a `filter_entry` set here replaces the pruning of excluded directories.
+    /// A later call replaces an earlier configurator.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// let processor = cfl::CflBuilder::new()
+    ///     .configure_walk(|walk: &mut cfl::WalkBuilder| {
+    ///         walk.max_depth(Some(2)).add_custom_ignore_filename(".cflignore");
+    ///     })
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn configure_walk(
+        mut self,
+        configure: impl Fn(&mut WalkBuilder) + Send + Sync + 'static,
+    ) -> Self {
+        self.options.configure_walk = Some(WalkConfigurator(Arc::new(configure)));
+        self
+    }
+
+    /// Stop walking once `flag` is set (e.g. from a Ctrl-C handler). The files collected
+    /// until then are still rendered, so the partial result can be emitted; see
+    /// [`FileProcessor::is_interrupted`].
+    pub fn interrupt_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.options.interrupt = Some(flag);
+        self
+    }
+
+    /// Only process files modified less than `window` ago, judged by their modification
+    /// time before they are read
+    pub fn modified_within(mut self, window: Duration) -> Self {
+        self.options.modified_within = Some(window);
+        self
+    }
+
+    /// Set size limits per file extension (without the leading dot), e.g. `json` => 50 KiB.
+    /// A limit of 0 drops every non-empty file of that type. Extensions without a limit
+    /// fall back to `max_size`.
+    pub fn max_size_per_ext(mut self, limits: HashMap<String, usize>) -> Self {
+        self.options.max_size_per_ext = limits;
+        self
+    }
+
+    /// Set token limits per language, as named by [`detect_language`](language::detect_language)
+    /// (e.g. `typescript` => 5000). Files are added in processing order while their
+    /// language stays within its limit; files that would exceed it are skipped and
+    /// reported with [`SkipReason::LanguageTokenLimit`]. Other languages are unlimited.
+    pub fn max_tokens_per_lang(mut self, limits: HashMap<String, usize>) -> Self {
+        self.options.max_tokens_per_lang = limits;
+        self
+    }
+
+    /// Treat lines longer than `chars` characters (e.g. minified code) according to
+    /// `long_line_action`
+    pub fn max_line_length(mut self, chars: usize) -> Self {
+        self.options.max_line_length = Some(chars);
+        self
+    }
+
+    /// Choose whether overly long lines are truncated (default) or the file is skipped
+    pub fn long_line_action(mut self, action: LongLineAction) -> Self {
+        self.options.long_line_action = action;
+        self
+    }
+
+    /// Skip files whose canonical path was already processed (default `true`).
+    /// Disable to emit the same file more than once in a single run.
+    pub fn dedup(mut self, enabled: bool) -> Self {
+        self.options.allow_duplicates = !enabled;
+        self
+    }
+
+    /// Set the text inserted between consecutive file blocks (default `"\n"`)
+    pub fn block_separator<S: Into<String>>(mut self, separator: S) -> Self {
+        self.options.block_separator = Some(separator.into());
+        self
+    }
+
+    /// Walk directories in filesystem order instead of by name. By default the entries of
+    /// each directory are sorted by name, so files are read and emitted in path order and
+    /// repeated runs over the same tree produce identical output on any machine.
+    pub fn unsorted_walk(mut self, enabled: bool) -> Self {
+        self.options.unsorted_walk = enabled;
+        self
+    }
+
+    /// List directories before files at each level of the directory structure
+    pub fn dirs_first(mut self, enabled: bool) -> Self {
+        self.options.dirs_first = enabled;
+        self
+    }
+
+    /// Include files that git tracks even if an ignore rule matches them
+    /// (e.g. force-added files), as listed by `git ls-files`
+    #[cfg(feature = "git")]
+    pub fn include_tracked_ignored(mut self, enabled: bool) -> Self {
+        self.options.include_tracked_ignored = enabled;
+        self
+    }
+
+    /// Prepend a header naming the branch and commit the output was generated from.
+    /// Use [`GitInfo::detect`] to look it up; `None` omits the header.
+    pub fn git_info(mut self, info: Option<GitInfo>) -> Self {
+        self.options.git_info = info;
+        self
+    }
+
+    /// Choose whether an unreadable file aborts processing, is skipped silently,
+    /// or is skipped with a warning (default)
+    pub fn on_error(mut self, policy: OnError) -> Self {
+        self.options.on_error = policy;
+        self
+    }
+
+    /// Truncate each file to roughly `tokens` tokens, cutting at a line boundary and
+    /// appending a `[... N more lines truncated]` marker
+    pub fn per_file_max_tokens(mut self, tokens: usize) -> Self {
+        self.options.per_file_max_tokens = Some(tokens);
+        self
+    }
+
+    /// Emit files passed directly to `process_path` first, in the order they were given,
+    /// instead of sorting or grouping them with walked files
+    pub fn preserve_arg_order(mut self, enabled: bool) -> Self {
+        self.options.preserve_arg_order = enabled;
+        self
+    }
+
+    /// Skip files that are empty or contain only whitespace
+    pub fn skip_empty(mut self, enabled: bool) -> Self {
+        self.options.skip_empty = enabled;
+        self
+    }
+
+    /// Precede each file block with a comment giving its size and token count
+    /// (e.g. `<!-- src/main.rs: 1,234 bytes, 210 tokens -->`)
+    pub fn annotate(mut self, enabled: bool) -> Self {
+        self.options.format.annotate = enabled;
+        self
+    }
+
+    /// Only copy text: skip files whose magic bytes identify a binary format (image,
+    /// archive, executable, ...) even if they would otherwise be decoded or embedded
+    #[cfg(feature = "sniff")]
+    pub fn text_only(mut self, enabled: bool) -> Self {
+        self.options.text_only = enabled;
+        self
+    }
+
+    /// Decode files that aren't valid UTF-8 with this encoding (e.g. `shift_jis`,
+    /// `latin1`) instead of skipping them as binary
+    pub fn fallback_encoding(mut self, encoding: Option<String>) -> Self {
+        self.options.fallback_encoding = encoding;
+        self
+    }
+
+    /// Rename displayed paths with `(from, to)` rules, matching either the exact relative
+    /// path or a directory prefix. The first matching rule wins; files are still read
+    /// from their real location and patterns match the original path.
+    pub fn path_rewrites(mut self, rewrites: Vec<(String, String)>) -> Self {
+        self.options.path_rewrites = rewrites;
+        self
+    }
+
+    /// Cap the number of entries collected for the directory structure; larger trees
+    /// are truncated with a warning
+    pub fn max_entries(mut self, entries: usize) -> Self {
+        self.options.max_entries = Some(entries);
+        self
+    }
+
+    /// Put files smaller than `bytes` into one shared markdown block, each preceded by
+    /// a `// path` line, to save per-file fence overhead
+    pub fn combine_under(mut self, bytes: usize) -> Self {
+        self.options.combine_under = Some(bytes);
+        self
+    }
+
+    /// Put the files smaller than `bytes` in each directory into one markdown block headed
+    /// by the directory, each preceded by a `// filename` line. Files alone in their
+    /// directory keep their own block.
+    pub fn combine_per_dir_under(mut self, bytes: usize) -> Self {
+        self.options.combine_per_dir_under = Some(bytes);
+        self
+    }
+
+    /// Show each file's path relative to the nearest ancestor containing a `Cargo.toml`
+    /// or `package.json`, for short project-local paths in monorepos
+    pub fn project_relative(mut self, enabled: bool) -> Self {
+        self.options.project_relative = enabled;
+        self
+    }
+
+    /// Start the output with a block recording the command that produced it
+    /// (e.g. from [`Cli::command_line`](crate::cli::Cli::command_line))
+    pub fn embed_command(mut self, command: Option<String>) -> Self {
+        self.options.command = command;
+        self
+    }
+
+    /// Include files with these exact names (comma-separated, e.g. `"Makefile,Dockerfile"`)
+    /// even when they don't match the include patterns. Exclude patterns still apply.
+    pub fn include_names<S: Into<String>>(mut self, names: S) -> Self {
+        self.options.include_names = names
+            .into()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        self
+    }
+
+    /// Stop adding content once the output reaches `bytes`, ending it with a truncation
+    /// notice; later files are still counted in the statistics. The limit applies before
+    /// any [`FileProcessor::set_output_transform`].
+    pub fn max_output_bytes(mut self, bytes: usize) -> Self {
+        self.options.max_output_bytes = Some(bytes);
+        self
+    }
+
+    /// Whether to keep each file's content and build the result (on by default). When off,
+    /// files are still read and counted, so [`FileProcessor::stats`] is complete while
+    /// [`FileProcessor::get_result`] stays empty.
+    pub fn collect_content(mut self, enabled: bool) -> Self {
+        self.options.skip_content = !enabled;
+        self
+    }
+
+    /// Show paths relative to the root of the git repository (the nearest ancestor of the
+    /// current directory containing `.git`), so they don't depend on the subdirectory cfl
+    /// runs in. Paths stay relative to the current directory outside a repository.
+    pub fn repo_relative(mut self, enabled: bool) -> Self {
+        self.options.repo_relative = enabled;
+        self
+    }
+
+    /// Emit a file whose content is identical to an earlier file's as a
+    /// `// (identical content as path)` reference instead of repeating it (experimental)
+    pub fn dedup_blocks(mut self, enabled: bool) -> Self {
+        self.options.dedup_blocks = enabled;
+        self
+    }
+
+    /// Order the files in the output by `key`. With [`group_by_dir`](Self::group_by_dir)
+    /// the files are sorted within each group, while groups stay ordered by directory.
+    pub fn sort(mut self, key: SortKey) -> Self {
+        self.options.sort = Some(key);
+        self
+    }
+
+    /// Only process these files, given relative to the current directory; other files
+    /// found while walking are left out. Files that don't exist are ignored. Include and
+    /// exclude patterns still apply.
+    pub fn only_files<I, P>(mut self, files: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.options.only_files = Some(files.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Never process these files, nor the files below these directories (e.g. the file a
+    /// previous run wrote its output to), given relative to the current directory. The
+    /// paths don't need to exist yet.
+    pub fn skip_paths<I, P>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        self.options.skip_paths = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Show a symlinked file at the real path of its target (relative to the current
+    /// directory when inside it) instead of the symlink's path. Only the displayed path
+    /// changes; patterns are still matched against the symlink's path.
+    pub fn resolve_symlinks_in_paths(mut self, enabled: bool) -> Self {
+        self.options.resolve_symlinks_in_paths = enabled;
+        self
+    }
+
+    /// Keep the original `\r\n` and `\r` line endings. By default they are converted to
+    /// `\n`, and sizes and token counts are those of the converted content. A file with
+    /// CRLF line endings also ends its last line with `\r\n` in the output.
+    pub fn keep_line_endings(mut self, enabled: bool) -> Self {
+        self.options.keep_line_endings = enabled;
+        self
+    }
+
+    /// Emit binary files smaller than `bytes` as a base64 block whose header names the
+    /// MIME type (e.g. ```` ```icon.png (image/png, base64) ````) instead of skipping them.
+    /// Larger binary files are still skipped.
+    pub fn embed_binary_under(mut self, bytes: usize) -> Self {
+        self.options.embed_binary_under = Some(bytes);
+        self
+    }
+
+    /// Also skip files matched by `.dockerignore` files, on top of the gitignore rules.
+    /// The rules are read with gitignore syntax from each directory's `.dockerignore`.
+    pub fn use_dockerignore(mut self, enabled: bool) -> Self {
+        self.options.use_dockerignore = enabled;
+        self
+    }
+
+    /// Emit each directory's `README*` file before the other files in that directory and
+    /// its subdirectories, after applying [`sort`](Self::sort)
+    pub fn readme_first(mut self, enabled: bool) -> Self {
+        self.options.readme_first = enabled;
+        self
+    }
+
+    /// Only run the full token estimator on files with these extensions (without the
+    /// leading dot, e.g. `rs`); other files get a cheap estimate of one token per 4 bytes
+    pub fn count_extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.count_extensions = Some(extensions.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Calibrate the token estimate for the tokenizer of `model` (generic by default)
+    pub fn token_model(mut self, model: TokenModel) -> Self {
+        self.options.token_model = model;
+        self
+    }
+
+    /// Replace the JSON of `.ipynb` files with the source of their cells, dropping outputs
+    /// and metadata: only code cells, or markdown cells too (as `#` comments)
+    #[cfg(feature = "notebook")]
+    pub fn notebook_cells(mut self, cells: NotebookCells) -> Self {
+        self.options.format.notebook_cells = Some(cells);
+        self
+    }
+
     pub fn build(self) -> Result<FileProcessor> {
-        FileProcessor::new(
-            &self.include_patterns,
-            &self.exclude_patterns,
-            &self.current_dir,
-        )
+        if let Some(label) = &self.options.fallback_encoding {
+            if encoding_rs::Encoding::for_label(label.as_bytes()).is_none() {
+                return Err(CflError::UnknownEncoding(label.clone()).into());
+            }
+        }
+        if let Some(template) = &self.options.format.template {
+            format::validate_template(template)?;
+        }
+
+        let mut exclude_patterns = self.exclude_patterns;
+        if self.default_excludes {
+            let defaults = processor::DEFAULT_EXCLUDED_DIRS.join(",");
+            exclude_patterns = Some(match exclude_patterns {
+                Some(patterns) if !patterns.trim().is_empty() => {
+                    format!("{},{}", patterns, defaults)
+                }
+                _ => defaults,
+            });
+        }
+
+        let processor =
+            FileProcessor::new(&self.include_patterns, &exclude_patterns, &self.current_dir)?;
+        Ok(processor.with_options(self.options))
     }
 }
 
@@ -106,3 +715,22 @@ pub fn copy_files_with_patterns<P: AsRef<Path>>(
     processor.process_path(path.as_ref())?;
     Ok(processor.get_result().to_string())
 }
+
+/// Count the files under `path` selected by `options` without assembling any output,
+/// the fastest way to find out how big a run would be
+///
+/// # Returns
+///
+/// The same statistics as [`FileProcessor::stats`] after a full run
+pub fn summarize<P: AsRef<Path>>(path: P, options: CflOptions) -> Result<Stats> {
+    let mut processor = CflBuilder::from_options(options)
+        .current_dir(path.as_ref())
+        .collect_content(false)
+        .build()?;
+
+    processor.process_path(path.as_ref())?;
+    Ok(processor.stats())
+}
+
+#[cfg(test)]
+mod tests;
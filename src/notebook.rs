@@ -0,0 +1,49 @@
+use clap::ValueEnum;
+use serde_json::Value;
+
+/// Which cells of a Jupyter notebook are kept with `notebook_cells`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum NotebookCells {
+    /// Only code cells
+    Code,
+    /// Code cells and markdown cells, the latter as `#` comments
+    All,
+}
+
+/// Replace the JSON of a notebook with the source of its cells, dropping outputs and metadata
+///
+/// Cells are separated by `# %%` markers (as in jupytext's percent format). Returns `None`
+/// when `content` isn't a notebook.
+pub(crate) fn extract_cells(content: &str, cells: NotebookCells) -> Option<String> {
+    let notebook: Value = serde_json::from_str(content).ok()?;
+    let mut extracted = Vec::new();
+
+    for cell in notebook.get("cells")?.as_array()? {
+        let source = cell_source(cell.get("source")?);
+        match cell.get("cell_type").and_then(Value::as_str) {
+            Some("code") => extracted.push(format!("# %%\n{}", source.trim_end())),
+            Some("markdown") if cells == NotebookCells::All => {
+                let commented: Vec<String> = source
+                    .trim_end()
+                    .lines()
+                    .map(|line| format!("# {}", line).trim_end().to_string())
+                    .collect();
+                extracted.push(format!("# %% [markdown]\n{}", commented.join("\n")));
+            }
+            _ => {}
+        }
+    }
+
+    let mut result = extracted.join("\n\n");
+    result.push('\n');
+    Some(result)
+}
+
+/// A cell's source, stored either as one string or as a list of lines
+fn cell_source(source: &Value) -> String {
+    match source {
+        Value::String(text) => text.clone(),
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
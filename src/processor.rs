@@ -1,31 +1,431 @@
+use crate::binary::{self, Classification};
+use crate::editorconfig::EditorConfig;
 use crate::error::CflError;
+use crate::format::{self, format_number, FormatOptions, OutputFormat, RenderOptions, SortKey};
+#[cfg(feature = "git")]
+use crate::git;
+use crate::git::GitInfo;
+use crate::language::detect_language;
+use crate::transform;
 use anyhow::Result;
+use clap::ValueEnum;
+use encoding_rs::Encoding;
 use glob::Pattern;
+use ignore::gitignore::GitignoreBuilder;
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::Match;
 use ignore::WalkBuilder;
-use std::collections::HashSet;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// Files marking the root of a project for `project_relative` paths
+const PROJECT_MANIFESTS: &[&str] = &["Cargo.toml", "package.json"];
+
+/// Directory below the current directory holding `*.include` and `*.ignore` pattern files
+pub const PATTERN_DIR: &str = ".cfl";
+
+/// Build, dependency and cache directories excluded by
+/// [`CflBuilder::apply_default_excludes`](crate::CflBuilder::apply_default_excludes)
+pub const DEFAULT_EXCLUDED_DIRS: &[&str] = &[
+    "target",
+    "node_modules",
+    "dist",
+    "build",
+    ".venv",
+    "venv",
+    "__pycache__",
+    ".mypy_cache",
+    ".pytest_cache",
+    ".tox",
+    ".next",
+    ".nuxt",
+    ".gradle",
+    ".terraform",
+    "coverage",
+];
 
 /// FileProcessor handles the core functionality of processing and copying files
 #[derive(Debug)]
 pub struct FileProcessor {
     include_patterns: Vec<Pattern>,
     exclude_patterns: Vec<Pattern>,
+    exception_patterns: Vec<Pattern>,
+    /// Patterns of the `*.include` files in [`PATTERN_DIR`]
+    dir_includes: Option<Override>,
+    /// Patterns of the `*.ignore` files in [`PATTERN_DIR`]
+    dir_excludes: Option<Override>,
     processed_paths: HashSet<PathBuf>,
     target_files: Vec<FileInfo>,
-    result: String,
+    skipped_files: Vec<SkippedFile>,
+    contents: Vec<String>,
+    /// Indices of files that were passed to `process_path` directly, in call order
+    explicit_files: Vec<usize>,
+    /// Number of files found by walking, before any filtering
+    candidates: usize,
+    /// Total bytes of the kept contents
+    content_bytes: usize,
+    /// Number of files whose content was kept before `max_output_bytes` was reached
+    kept_files: Option<usize>,
+    /// The formatted output, built on first access and reset whenever the collected files
+    /// or the rendering options change
+    rendered: OnceLock<Rendered>,
     current_dir: PathBuf,
+    /// Root of the git repository containing `current_dir`, with `repo_relative`
+    repo_root: Option<PathBuf>,
+    /// Canonical paths of the `only_files` that exist
+    only_paths: Option<HashSet<PathBuf>>,
+    /// Canonical paths of the `skip_paths`
+    skip_paths: Vec<PathBuf>,
+    /// Tokens collected so far for each language with a `max_tokens_per_lang` limit
+    language_tokens: HashMap<&'static str, usize>,
+    options: ProcessorOptions,
+}
+
+/// The formatted output of the collected files
+#[derive(Debug, Default)]
+struct Rendered {
+    result: String,
+    /// Whether the result was cut at `max_output_bytes`
+    truncated: bool,
+}
+
+/// A caller-supplied adjustment of the walker, applied after the default configuration
+#[derive(Clone)]
+pub(crate) struct WalkConfigurator(pub(crate) Arc<dyn Fn(&mut WalkBuilder) + Send + Sync>);
+
+impl std::fmt::Debug for WalkConfigurator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("WalkConfigurator")
+    }
+}
+
+/// A caller-supplied rewrite of the whole assembled result
+#[derive(Clone)]
+pub(crate) struct OutputTransform(Arc<dyn Fn(String) -> String + Send + Sync>);
+
+impl std::fmt::Debug for OutputTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("OutputTransform")
+    }
+}
+
+/// Options controlling how files are collected and rendered
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ProcessorOptions {
+    /// Emit a `## dir/` heading before the files of each top-level directory
+    pub(crate) group_by_dir: bool,
+    /// Normalize content according to the nearest `.editorconfig`
+    pub(crate) editorconfig: bool,
+    /// Options applied to each file's content and block
+    pub(crate) format: FormatOptions,
+    /// Classify files as binary or text using `.gitattributes` before sniffing content
+    pub(crate) respect_binary_gitattributes: bool,
+    /// Fail with an error instead of skipping binary files
+    pub(crate) fail_on_binary: bool,
+    /// Skip files whose content sniffs as a known binary format, whatever else applies
+    #[cfg(feature = "sniff")]
+    pub(crate) text_only: bool,
+    /// Include directories without any files in the directory structure
+    pub(crate) show_empty_dirs: bool,
+    /// Append a summary of the processed files at the end of the output
+    pub(crate) footer_stats: bool,
+    /// Price in dollars per 1,000 tokens shown in the footer
+    pub(crate) price_per_1k_tokens: Option<f64>,
+    /// Skip files larger than this many bytes
+    pub(crate) max_size: Option<usize>,
+    /// Per-extension size limits overriding `max_size`
+    pub(crate) max_size_per_ext: HashMap<String, usize>,
+    /// Adjustment of the walker for collecting files
+    pub(crate) configure_walk: Option<WalkConfigurator>,
+    /// Only process files modified less than this long ago
+    pub(crate) modified_within: Option<Duration>,
+    /// Token limits per language (e.g. `typescript`); files that would exceed one are skipped
+    pub(crate) max_tokens_per_lang: HashMap<String, usize>,
+    /// Lines longer than this many characters trigger `long_line_action`
+    pub(crate) max_line_length: Option<usize>,
+    /// What to do with files containing overly long lines
+    pub(crate) long_line_action: LongLineAction,
+    /// Process a file again even if its canonical path was already processed
+    pub(crate) allow_duplicates: bool,
+    /// Text inserted between consecutive file blocks (`"\n"` when unset)
+    pub(crate) block_separator: Option<String>,
+    /// List directories before files at each level of the directory structure
+    pub(crate) dirs_first: bool,
+    /// Include git-tracked files even when an ignore rule would exclude them
+    #[cfg(feature = "git")]
+    pub(crate) include_tracked_ignored: bool,
+    /// Branch and commit named in a header at the start of the output
+    pub(crate) git_info: Option<GitInfo>,
+    /// What to do when a file can't be read
+    pub(crate) on_error: OnError,
+    /// Truncate each file to roughly this many tokens, at a line boundary
+    pub(crate) per_file_max_tokens: Option<usize>,
+    /// Emit files passed directly to `process_path` first, in the order they were given
+    pub(crate) preserve_arg_order: bool,
+    /// Skip files that are empty or contain only whitespace
+    pub(crate) skip_empty: bool,
+    /// Encoding label (e.g. `shift_jis`) used to decode files that aren't valid UTF-8
+    pub(crate) fallback_encoding: Option<String>,
+    /// `(from, to)` rules renaming displayed relative paths
+    pub(crate) path_rewrites: Vec<(String, String)>,
+    /// Stop collecting the directory structure after this many entries
+    pub(crate) max_entries: Option<usize>,
+    /// Combine files smaller than this many bytes into a single markdown block
+    pub(crate) combine_under: Option<usize>,
+    /// Combine files smaller than this many bytes into one markdown block per directory
+    pub(crate) combine_per_dir_under: Option<usize>,
+    /// Show paths relative to the nearest directory containing a project manifest
+    pub(crate) project_relative: bool,
+    /// Command line recorded in a block at the start of the output
+    pub(crate) command: Option<String>,
+    /// Exact file names (e.g. `Makefile`) included regardless of the include patterns
+    pub(crate) include_names: Vec<String>,
+    /// Stop adding content to the output once it reaches this many bytes
+    pub(crate) max_output_bytes: Option<usize>,
+    /// Show paths relative to the root of the git repository containing the current directory
+    pub(crate) repo_relative: bool,
+    /// Emit files identical to an earlier file as a reference to it
+    pub(crate) dedup_blocks: bool,
+    /// Order of the files in the output, within each group with `group_by_dir`
+    pub(crate) sort: Option<SortKey>,
+    /// Emit each directory's `README*` file before the other files in that directory
+    pub(crate) readme_first: bool,
+    /// Only process these files (relative to the current directory), e.g. the staged files
+    pub(crate) only_files: Option<Vec<PathBuf>>,
+    /// Files, and directories whose files, are never processed (e.g. the output file)
+    pub(crate) skip_paths: Vec<PathBuf>,
+    /// Show symlinked files at the path of their target instead of the link's path
+    pub(crate) resolve_symlinks_in_paths: bool,
+    /// Keep `\r\n` and lone `\r` line endings instead of converting them to `\n`
+    pub(crate) keep_line_endings: bool,
+    /// Emit binary files smaller than this many bytes as base64 instead of skipping them
+    pub(crate) embed_binary_under: Option<usize>,
+    /// Don't apply `.gitignore` rules during the walk
+    pub(crate) ignore_gitignore: bool,
+    /// Also apply the rules of `.dockerignore` files during the walk
+    pub(crate) use_dockerignore: bool,
+    /// Extensions whose files get the full token estimate; others get a size-based one
+    pub(crate) count_extensions: Option<HashSet<String>>,
+    /// Prefix added to every non-empty line of the output
+    pub(crate) base_indent: String,
+    /// Calibration of the token estimate
+    pub(crate) token_model: TokenModel,
+    /// Only gather statistics: keep no file content and build no result
+    pub(crate) skip_content: bool,
+    /// Final pass over the assembled result, after the `max_output_bytes` truncation
+    pub(crate) output_transform: Option<OutputTransform>,
+    /// Flag that stops processing when set, keeping the files collected so far
+    pub(crate) interrupt: Option<Arc<AtomicBool>>,
+    /// Walk directories in filesystem order instead of sorting their entries by name
+    pub(crate) unsorted_walk: bool,
+}
+
+/// What to do with a file containing a line longer than the configured maximum
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LongLineAction {
+    /// Cut long lines with a `[... N chars truncated]` marker
+    #[default]
+    Truncate,
+    /// Skip the file as minified
+    Skip,
+}
+
+/// Target model whose tokenizer the token estimate is calibrated for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TokenModel {
+    /// One token per word and per punctuation character, independent of any model
+    #[default]
+    Generic,
+    /// OpenAI GPT-4 (`cl100k_base`): about 4 characters per token
+    Gpt4,
+    /// Anthropic Claude: about 3.5 characters per token
+    Claude,
+    /// Google Gemini: about 4.5 characters per token, punctuation often merged
+    Gemini,
+}
+
+impl TokenModel {
+    /// Characters per token within a word and the tokens counted per punctuation
+    /// character, or `None` for the generic estimate
+    fn calibration(self) -> Option<(f64, f64)> {
+        match self {
+            Self::Generic => None,
+            Self::Gpt4 => Some((4.0, 1.0)),
+            Self::Claude => Some((3.5, 1.0)),
+            Self::Gemini => Some((4.5, 0.75)),
+        }
+    }
+
+    /// Estimate the number of tokens in `content` for this model
+    pub fn estimate(self, content: &str) -> usize {
+        let Some((chars_per_token, punctuation_weight)) = self.calibration() else {
+            return estimate_tokens(content);
+        };
+
+        let words: f64 = content
+            .split(|c: char| c.is_whitespace() || c.is_ascii_punctuation())
+            .filter(|word| !word.is_empty())
+            .map(|word| (word.chars().count() as f64 / chars_per_token).ceil())
+            .sum();
+        let punctuation = content.chars().filter(char::is_ascii_punctuation).count();
+        (words + punctuation as f64 * punctuation_weight).round() as usize
+    }
+}
+
+/// What to do when a file can't be read
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnError {
+    /// Stop processing and return the error
+    Abort,
+    /// Skip the file without a warning
+    Skip,
+    /// Skip the file and log a warning
+    #[default]
+    Warn,
 }
 
 /// Information about a processed file
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileInfo {
     /// Relative path of the file
     pub path: String,
-    /// Size of the file in bytes
+    /// Size in bytes of the content as read and emitted, not the size reported before reading
     pub size: usize,
     /// Estimated number of tokens in the file
     pub tokens: usize,
+    /// Short content hash (first 8 hex digits of SHA-256), when hashing is enabled
+    pub hash: Option<String>,
+    /// MIME type of a small binary file embedded as base64 (e.g. `image/png`)
+    pub mime: Option<String>,
+}
+
+/// Summary statistics of the processed files
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stats {
+    /// Number of processed files
+    pub files: usize,
+    /// Total size of the processed files in bytes
+    pub total_size: usize,
+    /// Total estimated number of tokens
+    pub total_tokens: usize,
+    /// Number of files per detected language
+    pub languages: BTreeMap<String, usize>,
+}
+
+/// Rough size of a run, as counted by [`FileProcessor::estimate`] without reading any file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Estimate {
+    /// Number of files that would be processed
+    pub files: usize,
+    /// Total size of those files in bytes
+    pub total_size: u64,
+}
+
+/// The ignore rule hiding a file, as reported by [`FileProcessor::ignore_reason`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IgnoreRule {
+    /// The ignore file containing the rule (e.g. `.gitignore`)
+    pub file: PathBuf,
+    /// Line number of the rule in `file`, starting at 1
+    pub line: usize,
+    /// The rule as written
+    pub pattern: String,
+}
+
+impl std::fmt::Display for IgnoreRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.file.display(), self.line, self.pattern)
+    }
+}
+
+/// Whether a file would be copied, as reported by [`FileProcessor::would_include`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Inclusion {
+    /// The file would be copied
+    Included,
+    /// The file would not be copied, for the given reason
+    Excluded(SkipReason),
+}
+
+/// Outcome of loading a file that passed the pattern checks
+enum Loaded {
+    Content(String),
+    /// A small binary file as base64, with its MIME type
+    Embedded(String, &'static str),
+    Skipped(SkipReason),
+}
+
+/// Outcome of processing one path passed to [`FileProcessor::process_paths`]
+#[derive(Debug)]
+pub struct PathResult {
+    /// The path that was processed
+    pub path: PathBuf,
+    /// Number of files added from this path
+    pub files_added: usize,
+    /// The error that stopped processing this path, if any
+    pub error: Option<anyhow::Error>,
+}
+
+/// A file that was found during the walk but not copied
+#[derive(Clone, Debug)]
+pub struct SkippedFile {
+    /// Relative path of the file
+    pub path: String,
+    /// Why the file was skipped
+    pub reason: SkipReason,
+}
+
+/// Reason a file was skipped
+///
+/// Only content-based reasons (binary, size, minified, empty), read failures skipped by
+/// the error policy and language token limits are recorded in
+/// [`FileProcessor::get_skipped_files`]; the others are reported by
+/// [`FileProcessor::would_include`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The file is hidden by gitignore or other ignore rules
+    Ignored,
+    /// The file matches an exclude pattern
+    ExcludePattern,
+    /// Include patterns are set and the file matches none of them
+    NotIncluded,
+    /// The file is binary, either by content, by `.gitattributes` or as sniffed with `text_only`
+    Binary,
+    /// The file exceeds the size limit for its extension or the global size limit
+    TooLarge,
+    /// The file has a line longer than the maximum line length (e.g. minified code)
+    Minified,
+    /// Reading the file failed and the error policy skipped it
+    Unreadable,
+    /// The file is empty or contains only whitespace
+    Empty,
+    /// The file would push its language over the language's token limit
+    LanguageTokenLimit,
+}
+
+impl SkipReason {
+    /// Short snake_case name of the reason, e.g. `too_large`
+    pub fn name(self) -> &'static str {
+        match self {
+            SkipReason::Ignored => "ignored",
+            SkipReason::ExcludePattern => "exclude_pattern",
+            SkipReason::NotIncluded => "not_included",
+            SkipReason::Binary => "binary",
+            SkipReason::TooLarge => "too_large",
+            SkipReason::Minified => "minified",
+            SkipReason::Unreadable => "unreadable",
+            SkipReason::Empty => "empty",
+            SkipReason::LanguageTokenLimit => "language_token_limit",
+        }
+    }
 }
 
 impl FileProcessor {
@@ -35,36 +435,75 @@ impl FileProcessor {
         exclude: &Option<String>,
         current_dir: &Path,
     ) -> Result<Self> {
-        let include_patterns = match include {
-            Some(patterns) => patterns
-                .split(',')
-                .map(Pattern::new)
-                .collect::<Result<Vec<_>, _>>()
-                .map_err(CflError::from)?,
-            None => Vec::new(),
-        };
+        let include_patterns = parse_patterns(include)?;
+        let exclude_patterns = parse_patterns(exclude)?;
 
-        let exclude_patterns = match exclude {
-            Some(patterns) => patterns
-                .split(',')
-                .map(Pattern::new)
-                .collect::<Result<Vec<_>, _>>()
-                .map_err(CflError::from)?,
-            None => Vec::new(),
-        };
+        // 除外パターン（または除外されたディレクトリ）の内側を指すパスパターンは、
+        // .gitignoreの`!pattern`のように例外として扱う。
+        // 除外パターンと同一のパターンは例外にせず、除外を優先する
+        let (exception_patterns, include_patterns): (Vec<_>, Vec<_>) =
+            include_patterns.into_iter().partition(|include| {
+                include.as_str().contains('/')
+                    && !exclude_patterns.contains(include)
+                    && exclude_patterns.iter().any(|exclude| {
+                        exclude.matches(include.as_str())
+                            || in_excluded_dir(std::slice::from_ref(exclude), include.as_str())
+                    })
+            });
+        let (dir_includes, dir_excludes) = load_pattern_dir(&current_dir.join(PATTERN_DIR))?;
 
         Ok(Self {
             include_patterns,
             exclude_patterns,
+            exception_patterns,
+            dir_includes,
+            dir_excludes,
             processed_paths: HashSet::new(),
             target_files: Vec::new(),
-            result: String::new(),
+            skipped_files: Vec::new(),
+            contents: Vec::new(),
+            explicit_files: Vec::new(),
+            candidates: 0,
+            content_bytes: 0,
+            kept_files: None,
+            rendered: OnceLock::new(),
             current_dir: current_dir.to_path_buf(),
+            repo_root: None,
+            only_paths: None,
+            skip_paths: Vec::new(),
+            language_tokens: HashMap::new(),
+            options: ProcessorOptions::default(),
         })
     }
 
+    /// Replaces the processing options
+    pub(crate) fn with_options(mut self, options: ProcessorOptions) -> Self {
+        self.repo_root = options
+            .repo_relative
+            .then(|| find_repo_root(&self.current_dir))
+            .flatten();
+        // 存在しないファイル（削除をステージしたファイルなど）は対象にならない
+        self.only_paths = options.only_files.as_ref().map(|files| {
+            files
+                .iter()
+                .filter_map(|file| self.current_dir.join(file).canonicalize().ok())
+                .collect()
+        });
+        // 出力ファイルは最初の実行時にはまだ存在しないことがある
+        self.skip_paths = options
+            .skip_paths
+            .iter()
+            .map(|path| canonicalize_lenient(&self.current_dir.join(path)))
+            .collect();
+        self.options = options;
+        self
+    }
+
     /// Process files in the specified path
     ///
+    /// A file is processed directly without a walk, so gitignore rules don't hide a file
+    /// that was named explicitly; include/exclude patterns still apply to it.
+    ///
     /// # Arguments
     ///
     /// * `path` - The path to process (file or directory)
@@ -93,22 +532,127 @@ impl FileProcessor {
             return Err(CflError::PathNotFound(path.display().to_string()).into());
         }
 
-        let walker = WalkBuilder::new(path)
-            .hidden(false)
-            .git_ignore(true)
-            .git_global(true)
-            .ignore(true)
-            .build();
+        // 明示的に指定されたファイルは走査せず、gitignore も適用しない
+        if path.is_file() {
+            let before = self.target_files.len();
+            self.candidates += 1;
+            self.process_file(path)?;
+            if self.target_files.len() > before {
+                self.explicit_files.push(before);
+            }
+            return Ok(());
+        }
+
+        self.walk(path, None)
+    }
+
+    /// Process the files below the current directory matching any of `globs`
+    /// (e.g. `src/**/*.rs`), respecting gitignore rules
+    ///
+    /// Each glob is matched against the path relative to the current directory, so
+    /// `Cargo.toml` only matches the file at the top. Include/exclude patterns still apply.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # fn main() -> anyhow::Result<()> {
+    /// let mut processor = cfl::CflBuilder::new().build()?;
+    /// processor.process_globs(&["src/**/*.rs", "Cargo.toml"])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn process_globs(&mut self, globs: &[&str]) -> Result<()> {
+        let overrides = self.glob_overrides(globs)?;
+        let current_dir = self.current_dir.clone();
+        self.walk(&current_dir, Some(overrides))
+    }
+
+    /// Quickly count the files below `path` in the git revision `rev` that
+    /// [`process_ref`](Self::process_ref) would process, without reading them, as
+    /// [`estimate`](Self::estimate) does for the working tree
+    #[cfg(feature = "git")]
+    pub fn estimate_ref(&self, rev: &str, path: &Path) -> Result<Estimate> {
+        let files = git::tree_files(&self.current_dir, rev, path)
+            .ok_or_else(|| CflError::GitRef(rev.to_string()))?;
+
+        let mut estimate = Estimate::default();
+        for (relative, _, size) in files {
+            let path = self.current_dir.join(&relative);
+            if self
+                .check_patterns(&path, &relative.to_string_lossy())
+                .is_some()
+            {
+                continue;
+            }
+            if self
+                .size_limit(&path)
+                .is_some_and(|limit| size > limit as u64)
+            {
+                continue;
+            }
+            estimate.files += 1;
+            estimate.total_size += size;
+        }
+        Ok(estimate)
+    }
+
+    /// Matcher of `globs`, anchored at the current directory
+    fn glob_overrides(&self, globs: &[&str]) -> Result<Override> {
+        let mut builder = OverrideBuilder::new(&self.current_dir);
+        for glob in globs {
+            // gitignoreの構文ではスラッシュを含まないグロブがどの階層にも一致するため、先頭に固定する
+            match glob.strip_prefix('/') {
+                Some(_) => builder.add(glob)?,
+                None => builder.add(&format!("/{}", glob))?,
+            };
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Walk `path`, processing each file the walk lets through that `globs` (if set) matches
+    fn walk(&mut self, path: &Path, globs: Option<Override>) -> Result<()> {
+        let Some(builder) = self.pruning_walk_builder(path) else {
+            log::debug!("Skipping excluded directory: {}", path.display());
+            return Ok(());
+        };
+
+        let walker = builder.build();
+        let mut walked = HashSet::new();
 
         for result in walker {
+            if self.is_interrupted() {
+                log::debug!("Interrupted while walking {}", path.display());
+                break;
+            }
             match result {
                 Ok(entry) => {
-                    if entry.file_type().map_or(false, |ft| ft.is_file()) {
+                    // .gitignoreの解析エラーなどはエントリに付与される
+                    if let Some(err) = entry.error() {
+                        log::warn!("Error walking directory: {}", err);
+                    }
+                    // 上書き設定としてWalkBuilderに渡すと無視ルールより優先されるため、ここで絞り込む
+                    let selected = globs
+                        .as_ref()
+                        .is_none_or(|globs| globs.matched(entry.path(), false).is_whitelist());
+                    if selected && entry.file_type().is_some_and(|ft| ft.is_file()) {
+                        self.candidates += 1;
                         self.process_file(entry.path())?;
+                        walked.insert(entry.into_path());
                     }
                 }
                 Err(err) => {
-                    eprintln!("Error walking directory: {}", err);
+                    log::warn!("Error walking directory: {}", err);
+                }
+            }
+        }
+
+        // 無視ルールに一致するがgitで追跡されているファイルを追加
+        #[cfg(feature = "git")]
+        if self.options.include_tracked_ignored && path.is_dir() && !self.is_interrupted() {
+            for tracked in git::tracked_files(path) {
+                if tracked.is_file() && !walked.contains(&tracked) {
+                    log::debug!("Including tracked but ignored file: {}", tracked.display());
+                    self.process_file(&tracked)?;
                 }
             }
         }
@@ -116,82 +660,870 @@ impl FileProcessor {
         Ok(())
     }
 
+    /// Quickly count the files below `path` that would be processed, without reading them
+    ///
+    /// Only the walk, the file selection (include/exclude patterns, `only_files`,
+    /// `modified_within`) and the size limits are applied, so files later skipped by
+    /// content (e.g. binary files) are still counted. Meant as a cheap preflight check
+    /// before [`process_path`](Self::process_path).
+    pub fn estimate(&self, path: &Path) -> Result<Estimate> {
+        if !path.exists() {
+            return Err(CflError::PathNotFound(path.display().to_string()).into());
+        }
+        Ok(self.estimate_walk(path, None))
+    }
+
+    /// Quickly count the files matching `globs` that
+    /// [`process_globs`](Self::process_globs) would process, as [`estimate`](Self::estimate)
+    /// does for a path
+    pub fn estimate_globs(&self, globs: &[&str]) -> Result<Estimate> {
+        let overrides = self.glob_overrides(globs)?;
+        Ok(self.estimate_walk(&self.current_dir, Some(&overrides)))
+    }
+
+    /// Count the files below `path` that `globs` (if set) matches and that pass the
+    /// file selection and size limits
+    fn estimate_walk(&self, path: &Path, globs: Option<&Override>) -> Estimate {
+        let mut estimate = Estimate::default();
+        let Some(builder) = self.pruning_walk_builder(path) else {
+            return estimate;
+        };
+        for entry in builder.build().filter_map(|result| result.ok()) {
+            if !entry.file_type().is_some_and(|ft| ft.is_file()) {
+                continue;
+            }
+            if globs.is_some_and(|globs| !globs.matched(entry.path(), false).is_whitelist()) {
+                continue;
+            }
+            let canonical_path =
+                fs::canonicalize(entry.path()).unwrap_or_else(|_| entry.path().to_path_buf());
+            let relative_path = self.relative_path(entry.path());
+            if self
+                .deselected(entry.path(), &canonical_path, &relative_path)
+                .is_some()
+            {
+                continue;
+            }
+            let size = entry.metadata().map_or(0, |metadata| metadata.len());
+            if self
+                .size_limit(entry.path())
+                .is_some_and(|limit| size > limit as u64)
+            {
+                continue;
+            }
+            estimate.files += 1;
+            estimate.total_size += size;
+        }
+
+        estimate
+    }
+
+    /// Forget all processed files, so the same paths can be processed again from scratch
+    pub fn clear(&mut self) {
+        self.processed_paths.clear();
+        self.target_files.clear();
+        self.skipped_files.clear();
+        self.contents.clear();
+        self.explicit_files.clear();
+        self.candidates = 0;
+        self.content_bytes = 0;
+        self.kept_files = None;
+        self.language_tokens.clear();
+        self.invalidate();
+    }
+
+    /// Process several paths, reporting the outcome of each one
+    ///
+    /// Unlike calling [`process_path`](Self::process_path) in a loop, an error on one path
+    /// doesn't abort the batch; it is recorded in that path's [`PathResult`].
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - The paths to process (files or directories)
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<PathResult>>` - One result per path, in the given order
+    pub fn process_paths(&mut self, paths: &[&Path]) -> Result<Vec<PathResult>> {
+        let mut results = Vec::with_capacity(paths.len());
+
+        for path in paths {
+            let before = self.target_files.len();
+            let error = self.process_path(path).err();
+            results.push(PathResult {
+                path: path.to_path_buf(),
+                files_added: self.target_files.len() - before,
+                error,
+            });
+        }
+
+        Ok(results)
+    }
+
     /// Process a single file
     fn process_file(&mut self, path: &Path) -> Result<()> {
-        let canonical_path = fs::canonicalize(path)?;
-        if self.processed_paths.contains(&canonical_path) {
+        let canonical_path = match fs::canonicalize(path) {
+            Ok(canonical_path) => canonical_path,
+            Err(err) => return self.read_failed(path, err.into()),
+        };
+        if !self.options.allow_duplicates && self.processed_paths.contains(&canonical_path) {
+            log::debug!("Skipping already processed file: {}", path.display());
             return Ok(());
         }
 
-        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let relative_path = self.relative_path(path);
+        if let Some(reason) = self.deselected(path, &canonical_path, &relative_path) {
+            log::debug!("Skipping {}: {}", reason, path.display());
+            return Ok(());
+        }
+
+        let loaded = match self.load_content(path, &canonical_path) {
+            Ok(loaded) => loaded,
+            Err(err) => return self.read_failed(path, err),
+        };
+        // パターンは元のパスで判定し、表示用のパスだけを書き換える
+        let relative_path = if self.options.resolve_symlinks_in_paths && path.is_symlink() {
+            let resolved = self.resolved_relative_path(&canonical_path);
+            self.display_path(&canonical_path, resolved)
+        } else {
+            self.display_path(path, relative_path)
+        };
+        self.check_binary(&relative_path, &loaded)?;
+        self.add_loaded(path, relative_path, loaded);
+        self.mark_processed(canonical_path);
 
+        Ok(())
+    }
+
+    /// Why the file at `path` is left out of the selection (the selected and skipped paths,
+    /// the include/exclude patterns and `modified_within`) before it is read, or `None`
+    /// when it is selected. Shared by processing and estimating.
+    fn deselected(
+        &self,
+        path: &Path,
+        canonical_path: &Path,
+        relative_path: &str,
+    ) -> Option<&'static str> {
+        if let Some(only_paths) = &self.only_paths {
+            if !only_paths.contains(canonical_path) {
+                return Some("file not in the selected files");
+            }
+        }
         if self
-            .exclude_patterns
+            .skip_paths
             .iter()
-            .any(|pattern| pattern.matches(file_name))
+            .any(|skip| canonical_path.starts_with(skip))
         {
-            return Ok(());
+            return Some("file in the skipped paths");
+        }
+        match self.check_patterns(path, relative_path) {
+            Some(SkipReason::ExcludePattern) => return Some("excluded file"),
+            Some(_) => return Some("file not matching include patterns"),
+            None => {}
         }
+        if let Some(window) = self.options.modified_within {
+            if !modified_within(path, window) {
+                return Some("file not modified recently");
+            }
+        }
+        None
+    }
 
-        if !self.include_patterns.is_empty()
-            && !self
-                .include_patterns
-                .iter()
-                .any(|pattern| pattern.matches(file_name))
-        {
-            return Ok(());
+    /// Process the files below `path` as they are in the git revision `rev` (e.g. `HEAD~1`),
+    /// reading their contents from git instead of the working tree
+    ///
+    /// Works without a working tree, e.g. in a bare clone. Gitignore rules don't apply,
+    /// while include/exclude patterns and the content checks do.
+    ///
+    /// # Arguments
+    ///
+    /// * `rev` - The revision whose tree is read
+    /// * `path` - The file or directory to process, relative to the current directory
+    #[cfg(feature = "git")]
+    pub fn process_ref(&mut self, rev: &str, path: &Path) -> Result<()> {
+        let files = git::tree_files(&self.current_dir, rev, path)
+            .ok_or_else(|| CflError::GitRef(rev.to_string()))?;
+
+        for (relative, name, _) in files {
+            if self.is_interrupted() {
+                log::debug!("Interrupted while reading {}", rev);
+                break;
+            }
+            let path = self.current_dir.join(&relative);
+            let relative_path = relative.to_string_lossy().to_string();
+            if let Some(reason) = self.check_patterns(&path, &relative_path) {
+                log::debug!("Skipping file ({:?}): {}", reason, relative_path);
+                continue;
+            }
+
+            let Some(bytes) = git::show_file(&self.current_dir, rev, &name) else {
+                self.read_failed(&path, anyhow::anyhow!("not readable from {}", rev))?;
+                continue;
+            };
+            let loaded = match self.size_limit(&path) {
+                Some(limit) if bytes.len() > limit => Loaded::Skipped(SkipReason::TooLarge),
+                _ => self.load_bytes(&path, &path, bytes),
+            };
+            let relative_path = self.display_path(&path, relative_path);
+            self.check_binary(&relative_path, &loaded)?;
+            self.add_loaded(&path, relative_path, loaded);
         }
 
-        let content = fs::read_to_string(path)?;
-        let relative_path = path
-            .strip_prefix(&self.current_dir)
+        Ok(())
+    }
+
+    /// Add a file that doesn't exist on disk, such as a generated summary or a diff, as if
+    /// it had been read with `content`
+    ///
+    /// The content goes through the same content checks and transformations as a read file
+    /// and the file takes part in the statistics, ordering and rendering. Include/exclude
+    /// patterns and gitignore rules don't apply, and `fail_on_binary` doesn't either:
+    /// content detected as binary is only recorded as skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path shown for the file, relative to the current directory
+    /// * `content` - The content of the file
+    pub fn add_virtual_file(&mut self, path: &str, content: &str) {
+        let full_path = self.current_dir.join(path);
+        let loaded = self.load_bytes(&full_path, &full_path, content.as_bytes().to_vec());
+        self.add_loaded(&full_path, path.to_string(), loaded);
+    }
+
+    /// Fail on a file skipped as binary when `fail_on_binary` is set
+    fn check_binary(&self, relative_path: &str, loaded: &Loaded) -> Result<()> {
+        if self.options.fail_on_binary && matches!(loaded, Loaded::Skipped(SkipReason::Binary)) {
+            return Err(CflError::BinaryFile(relative_path.to_string()).into());
+        }
+        Ok(())
+    }
+
+    /// Record a loaded file as processed, or as skipped with the reason
+    fn add_loaded(&mut self, path: &Path, relative_path: String, loaded: Loaded) {
+        let (content, mime) = match loaded {
+            Loaded::Content(content) => (content, None),
+            Loaded::Embedded(content, mime) => (content, Some(mime.to_string())),
+            Loaded::Skipped(reason) => {
+                log::debug!("Skipping file ({:?}): {}", reason, path.display());
+                self.skipped_files.push(SkippedFile {
+                    path: relative_path,
+                    reason,
+                });
+                return;
+            }
+        };
+
+        let tokens = match &self.options.count_extensions {
+            Some(extensions) if !has_extension_in(path, extensions) => {
+                quick_token_estimate(&content)
+            }
+            _ => self.options.token_model.estimate(&content),
+        };
+        if let Some(language) = detect_language(path) {
+            if let Some(&limit) = self.options.max_tokens_per_lang.get(language) {
+                let used = self.language_tokens.entry(language).or_insert(0);
+                if *used + tokens > limit {
+                    log::debug!(
+                        "Skipping file over the {} token limit: {}",
+                        language,
+                        path.display()
+                    );
+                    self.skipped_files.push(SkippedFile {
+                        path: relative_path,
+                        reason: SkipReason::LanguageTokenLimit,
+                    });
+                    return;
+                }
+                *used += tokens;
+            }
+        }
+
+        let mut file = format::file_info(&relative_path, &content, tokens, &self.options.format);
+        file.mime = mime;
+        // 出力の上限に達した後は統計だけを残し、内容は保持しない
+        match self.options.max_output_bytes {
+            Some(limit) if self.kept_files.is_none() && self.content_bytes >= limit => {
+                self.kept_files = Some(self.target_files.len());
+                self.contents.push(String::new());
+            }
+            _ if self.kept_files.is_some() || self.options.skip_content => {
+                self.contents.push(String::new())
+            }
+            _ => {
+                self.content_bytes += content.len();
+                self.contents.push(content);
+            }
+        }
+        self.target_files.push(file);
+        self.invalidate();
+    }
+
+    /// Handle a file that couldn't be read according to the `on_error` policy
+    fn read_failed(&mut self, path: &Path, err: anyhow::Error) -> Result<()> {
+        match self.options.on_error {
+            OnError::Abort => {
+                return Err(err.context(format!("Failed to read file: {}", path.display())));
+            }
+            OnError::Skip => {
+                log::debug!("Skipping unreadable file {}: {}", path.display(), err);
+            }
+            OnError::Warn => {
+                log::warn!("Skipping unreadable file {}: {}", path.display(), err);
+            }
+        }
+
+        self.skipped_files.push(SkippedFile {
+            path: self.display_path(path, self.relative_path(path)),
+            reason: SkipReason::Unreadable,
+        });
+        Ok(())
+    }
+
+    /// Remember a processed file so later calls skip it, unless duplicates are allowed
+    fn mark_processed(&mut self, canonical_path: PathBuf) {
+        if !self.options.allow_duplicates {
+            self.processed_paths.insert(canonical_path);
+        }
+    }
+
+    /// Find the ignore rule that hides `path` from the walk, to explain why it isn't copied
+    ///
+    /// The `.gitignore` and `.ignore` files (and `.dockerignore` with `use_dockerignore`)
+    /// from the file's directory up to the repository root are searched; as in the walk,
+    /// deeper files take precedence and a `!pattern` re-includes the file. A rule matching
+    /// a parent directory (e.g. `build/`) counts as well.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<IgnoreRule>>` - The matching rule, or `None` if no rule ignores the file
+    pub fn ignore_reason(&self, path: &Path) -> Result<Option<IgnoreRule>> {
+        if !path.exists() {
+            return Err(CflError::PathNotFound(path.display().to_string()).into());
+        }
+        let absolute = std::path::absolute(path)?;
+        let mut ignore_files = vec![".ignore", ".gitignore"];
+        if self.options.use_dockerignore {
+            ignore_files.insert(0, ".dockerignore");
+        }
+
+        for dir in absolute.ancestors().skip(1) {
+            for name in &ignore_files {
+                let file = dir.join(name);
+                if !file.is_file() {
+                    continue;
+                }
+                let mut builder = GitignoreBuilder::new(dir);
+                if let Some(err) = builder.add(&file) {
+                    log::warn!("Error reading {}: {}", file.display(), err);
+                }
+                let matcher = builder.build()?;
+                match matcher.matched_path_or_any_parents(&absolute, absolute.is_dir()) {
+                    Match::Ignore(glob) => {
+                        let pattern = glob.original().to_string();
+                        let line = fs::read_to_string(&file)?
+                            .lines()
+                            .position(|line| line.trim() == pattern)
+                            .map_or(0, |index| index + 1);
+                        return Ok(Some(IgnoreRule {
+                            file,
+                            line,
+                            pattern,
+                        }));
+                    }
+                    Match::Whitelist(_) => return Ok(None),
+                    Match::None => {}
+                }
+            }
+            if dir.join(".git").exists() {
+                break;
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Check whether a file would be copied with the current settings, without walking
+    ///
+    /// Applies the same checks as [`process_path`](Self::process_path): gitignore rules,
+    /// include/exclude patterns, size limits, binary detection and long-line handling.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to check
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Inclusion>` - Whether the file is included, or why it is excluded
+    pub fn would_include(&self, path: &Path) -> Result<Inclusion> {
+        if !path.is_file() {
+            return Err(CflError::PathNotFound(path.display().to_string()).into());
+        }
+
+        if self.is_ignored(path)? {
+            return Ok(Inclusion::Excluded(SkipReason::Ignored));
+        }
+
+        let relative_path = self.relative_path(path);
+        if let Some(reason) = self.check_patterns(path, &relative_path) {
+            return Ok(Inclusion::Excluded(reason));
+        }
+
+        match self.load_content(path, &fs::canonicalize(path)?)? {
+            Loaded::Content(_) | Loaded::Embedded(..) => Ok(Inclusion::Included),
+            Loaded::Skipped(reason) => Ok(Inclusion::Excluded(reason)),
+        }
+    }
+
+    /// Path of a file relative to the current directory, as shown in the output
+    fn relative_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.current_dir)
             .unwrap_or(path)
             .to_string_lossy()
-            .to_string();
+            .to_string()
+    }
 
-        let size = content.len();
-        let tokens = self.estimate_tokens(&content);
+    /// A canonical path relative to the canonical current directory, or the whole
+    /// canonical path when it lies outside
+    fn resolved_relative_path(&self, canonical_path: &Path) -> String {
+        let current_dir = fs::canonicalize(&self.current_dir).unwrap_or_default();
+        canonical_path
+            .strip_prefix(&current_dir)
+            .unwrap_or(canonical_path)
+            .to_string_lossy()
+            .to_string()
+    }
 
-        self.target_files.push(FileInfo {
-            path: relative_path.clone(),
-            size,
-            tokens,
+    /// Path of a file as shown in the output: relative to its project when
+    /// `project_relative` is set or to the repository root with `repo_relative`, then
+    /// with the first matching `path_rewrites` rule applied.
+    /// A rule matches the exact path or, as a directory prefix, anything below it.
+    fn display_path(&self, path: &Path, relative_path: String) -> String {
+        let repo_path = self.repo_root.as_ref().and_then(|root| {
+            let absolute = std::path::absolute(path).ok()?;
+            Some(
+                absolute
+                    .strip_prefix(root)
+                    .ok()?
+                    .to_string_lossy()
+                    .to_string(),
+            )
         });
+        let relative_path = repo_path.unwrap_or(relative_path);
 
-        self.result
-            .push_str(&format!("```{}\n{}\n```\n", relative_path, content));
-        self.processed_paths.insert(canonical_path);
+        let project_root = self
+            .options
+            .project_relative
+            .then(|| self.project_root(path))
+            .flatten();
+        let relative_path = match project_root.and_then(|root| path.strip_prefix(root).ok()) {
+            Some(project_path) => project_path.to_string_lossy().to_string(),
+            None => relative_path,
+        };
 
-        Ok(())
+        for (from, to) in &self.options.path_rewrites {
+            let from = from.trim_end_matches('/');
+            if relative_path == from {
+                return to.clone();
+            }
+            if let Some(rest) = relative_path
+                .strip_prefix(from)
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                return format!("{}/{}", to.trim_end_matches('/'), rest);
+            }
+        }
+        relative_path
     }
 
-    /// Estimate the number of tokens in a string
-    fn estimate_tokens(&self, content: &str) -> usize {
-        content
-            .split(|c: char| {
-                c.is_whitespace()
-                    || c.is_ascii_punctuation()
-                    || matches!(c, '(' | ')' | '[' | ']' | '{' | '}')
-                    || matches!(
-                        c,
-                        '+' | '-'
-                            | '*'
-                            | '/'
-                            | '='
-                            | '<'
-                            | '>'
-                            | '&'
-                            | '|'
-                            | '!'
-                            | '@'
-                            | '#'
-                            | '$'
-                            | '%'
-                            | '^'
-                    )
+    /// Nearest ancestor directory of `path` containing a project manifest.
+    ///
+    /// For files below the current directory the search stops there, so a manifest
+    /// above the directory cfl was run from is never used.
+    fn project_root<'a>(&self, path: &'a Path) -> Option<&'a Path> {
+        let within_current_dir = path.starts_with(&self.current_dir);
+        path.ancestors()
+            .skip(1)
+            .take_while(|dir| !within_current_dir || dir.starts_with(&self.current_dir))
+            .find(|dir| {
+                PROJECT_MANIFESTS
+                    .iter()
+                    .any(|manifest| dir.join(manifest).is_file())
             })
-            .filter(|s| !s.is_empty())
-            .count()
+    }
+
+    /// Apply the include/exclude patterns, returning why the file is filtered out.
+    ///
+    /// Exclude patterns win over include patterns when both match, except for
+    /// exception patterns (include path patterns inside an exclude pattern). Files in a
+    /// directory matching an exclude pattern are excluded too.
+    /// Files named in `include_names` count as included.
+    fn check_patterns(&self, path: &Path, relative_path: &str) -> Option<SkipReason> {
+        if matches_any(&self.exception_patterns, path, relative_path) {
+            return None;
+        }
+
+        if matches_any(&self.exclude_patterns, path, relative_path)
+            || in_excluded_dir(&self.exclude_patterns, relative_path)
+            || self.dir_excluded(relative_path)
+        {
+            return Some(SkipReason::ExcludePattern);
+        }
+
+        // -i のパターンと .cfl/*.include のパターンは和集合として扱う
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if (!self.include_patterns.is_empty() || self.dir_includes.is_some())
+            && !matches_any(&self.include_patterns, path, relative_path)
+            && !self
+                .dir_includes
+                .as_ref()
+                .is_some_and(|includes| includes.matched(relative_path, false).is_whitelist())
+            && !self
+                .options
+                .include_names
+                .iter()
+                .any(|name| name == file_name)
+        {
+            return Some(SkipReason::NotIncluded);
+        }
+
+        None
+    }
+
+    /// Whether a `.cfl/*.ignore` pattern matches the file or a directory containing it
+    fn dir_excluded(&self, relative_path: &str) -> bool {
+        let Some(excludes) = &self.dir_excludes else {
+            return false;
+        };
+        let path = Path::new(relative_path);
+        excludes.matched(path, false).is_ignore()
+            || path
+                .ancestors()
+                .skip(1)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .any(|dir| excludes.matched(dir, true).is_ignore())
+    }
+
+    /// Read a file and apply the content checks and transformations
+    fn load_content(&self, path: &Path, canonical_path: &Path) -> Result<Loaded> {
+        let limit = self.size_limit(path);
+        if let Some(limit) = limit {
+            if fs::metadata(path)?.len() > limit as u64 {
+                return Ok(Loaded::Skipped(SkipReason::TooLarge));
+            }
+        }
+
+        // 確認後に書き込まれて大きくなったファイルも、読み込んだ内容で判定する
+        match read_limited(fs::File::open(path)?, limit)? {
+            Some(bytes) => Ok(self.load_bytes(path, canonical_path, bytes)),
+            None => Ok(Loaded::Skipped(SkipReason::TooLarge)),
+        }
+    }
+
+    /// Apply the content checks and transformations to the raw content of a file
+    fn load_bytes(&self, path: &Path, canonical_path: &Path, bytes: Vec<u8>) -> Loaded {
+        #[cfg(feature = "sniff")]
+        if self.options.text_only && binary::sniffs_binary(&bytes) {
+            return Loaded::Skipped(SkipReason::Binary);
+        }
+        let classification = if self.options.respect_binary_gitattributes {
+            binary::gitattributes_classification(canonical_path)
+        } else {
+            None
+        };
+        let content = match classification {
+            Some(Classification::Binary) => None,
+            Some(Classification::Text) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+            None => self.decode(&bytes),
+        };
+        let Some(mut content) = content else {
+            return match self.options.embed_binary_under {
+                Some(limit) if bytes.len() < limit => {
+                    Loaded::Embedded(binary::base64_block(&bytes), binary::mime_type(path))
+                }
+                _ => Loaded::Skipped(SkipReason::Binary),
+            };
+        };
+        if self.options.skip_empty && content.trim().is_empty() {
+            return Loaded::Skipped(SkipReason::Empty);
+        }
+
+        if !self.options.keep_line_endings && content.contains('\r') {
+            content = transform::normalize_line_endings(&content);
+        }
+        if self.options.editorconfig {
+            content = EditorConfig::for_file(canonical_path).apply(&content);
+        }
+        if let Some(max_length) = self.options.max_line_length {
+            if transform::has_long_line(&content, max_length) {
+                match self.options.long_line_action {
+                    LongLineAction::Truncate => {
+                        content = transform::truncate_long_lines(&content, max_length);
+                    }
+                    LongLineAction::Skip => return Loaded::Skipped(SkipReason::Minified),
+                }
+            }
+        }
+
+        let mut content = format::transform_content(path, &content, &self.options.format);
+        if let Some(max_tokens) = self.options.per_file_max_tokens {
+            content = transform::truncate_to_tokens(&content, max_tokens, self.options.token_model);
+        }
+
+        Loaded::Content(content)
+    }
+
+    /// Decode file content as UTF-8, falling back to `fallback_encoding` if it's set.
+    /// Returns `None` for content that looks binary or doesn't decode cleanly.
+    fn decode(&self, bytes: &[u8]) -> Option<String> {
+        if !binary::looks_binary(bytes) {
+            return String::from_utf8(bytes.to_vec()).ok();
+        }
+        if binary::has_leading_nul(bytes) {
+            return None;
+        }
+
+        let encoding = Encoding::for_label(self.options.fallback_encoding.as_ref()?.as_bytes())?;
+        encoding
+            .decode_without_bom_handling_and_without_replacement(bytes)
+            .map(|content| content.into_owned())
+    }
+
+    /// Check whether gitignore rules hide the file from the walk.
+    ///
+    /// Each directory level between the current directory and the file is checked,
+    /// since an ignored parent directory hides everything below it.
+    fn is_ignored(&self, path: &Path) -> Result<bool> {
+        let absolute = std::path::absolute(path)?;
+        let root = std::path::absolute(&self.current_dir)?;
+        let chain: Vec<&Path> = absolute.ancestors().collect();
+
+        for pair in chain.windows(2) {
+            let (child, dir) = (pair[0], pair[1]);
+            if child != absolute && !dir.starts_with(&root) {
+                break;
+            }
+            let visible = self
+                .walk_builder(dir)
+                .max_depth(Some(1))
+                .build()
+                .filter_map(Result::ok)
+                .any(|entry| entry.depth() == 1 && entry.path() == child);
+            if !visible {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Create a walker that doesn't descend into excluded directories, or `None` when
+    /// `path` itself is an excluded directory
+    fn pruning_walk_builder(&self, path: &Path) -> Option<WalkBuilder> {
+        let excluded_dirs = ExcludedDirs {
+            exclude_patterns: self.exclude_patterns.clone(),
+            exception_patterns: self.exception_patterns.clone(),
+            current_dir: self.current_dir.clone(),
+        };
+        if path.is_dir() && excluded_dirs.prunes(path) {
+            return None;
+        }
+
+        let mut builder = self.walk_builder(path);
+        // 除外されたディレクトリは配下を走査しない
+        builder.filter_entry(move |entry| {
+            !(entry.file_type().is_some_and(|ft| ft.is_dir()) && excluded_dirs.prunes(entry.path()))
+        });
+        if let Some(WalkConfigurator(configure)) = &self.options.configure_walk {
+            configure(&mut builder);
+        }
+        Some(builder)
+    }
+
+    /// Create a walker configured the way every walk in this processor is done
+    fn walk_builder(&self, path: &Path) -> WalkBuilder {
+        let mut builder = WalkBuilder::new(path);
+        let gitignore = !self.options.ignore_gitignore;
+        builder
+            .hidden(false)
+            .git_ignore(gitignore)
+            .git_global(gitignore)
+            .git_exclude(gitignore)
+            .ignore(true);
+        if self.options.use_dockerignore {
+            builder.add_custom_ignore_filename(".dockerignore");
+        }
+        // ファイルシステムに依存しない順序で読み込み、出力を再現可能にする
+        if !self.options.unsorted_walk {
+            builder.sort_by_file_name(|a, b| a.cmp(b));
+        }
+        builder
+    }
+
+    /// The size limit for a file: its extension's limit if set, otherwise the global one
+    fn size_limit(&self, path: &Path) -> Option<usize> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.options.max_size_per_ext.get(ext))
+            .copied()
+            .or(self.options.max_size)
+    }
+
+    /// Drop the formatted result so it is rebuilt on the next access
+    fn invalidate(&mut self) {
+        self.rendered = OnceLock::new();
+    }
+
+    /// The formatted result, rendering the collected files if they changed since the last
+    /// access
+    fn rendered(&self) -> &Rendered {
+        self.rendered.get_or_init(|| self.render())
+    }
+
+    /// Build the formatted result from the collected files
+    fn render(&self) -> Rendered {
+        if self.options.skip_content {
+            return Rendered::default();
+        }
+        let (explicit, walked) = self.render_order();
+        let kept = self.kept_files.unwrap_or(self.target_files.len());
+        let files = |indices: Vec<usize>| -> Vec<(&FileInfo, &str)> {
+            indices
+                .into_iter()
+                .filter(|&index| index < kept)
+                .map(|index| (&self.target_files[index], self.contents[index].as_str()))
+                .collect()
+        };
+        let mut result =
+            format::render_sections(&files(explicit), &files(walked), &self.render_options());
+        let truncated = match self.options.max_output_bytes {
+            Some(limit) => self.truncate_output(&mut result, limit),
+            None => false,
+        };
+        let result = match &self.options.output_transform {
+            Some(transform) => (transform.0)(result),
+            None => result,
+        };
+        Rendered { result, truncated }
+    }
+
+    /// Cut the output at `limit` bytes, ending it with a notice when anything was left out.
+    /// Returns whether the output was truncated.
+    fn truncate_output(&self, result: &mut String, limit: usize) -> bool {
+        if result.len() <= limit && self.kept_files.is_none() {
+            return false;
+        }
+
+        let mut end = limit.min(result.len());
+        while !result.is_char_boundary(end) {
+            end -= 1;
+        }
+        if self.options.format.format.is_structured() {
+            // 途中で切れた行は不正なJSONになるので、行単位で切る
+            end = result[..end].rfind('\n').map_or(0, |newline| newline + 1);
+        }
+        result.truncate(end);
+
+        let notice = format!(
+            "Output truncated at {} bytes; {} files were counted",
+            format_number(limit),
+            format_number(self.target_files.len())
+        );
+        if !result.is_empty() && !result.ends_with('\n') {
+            result.push('\n');
+        }
+        match self.options.format.format {
+            OutputFormat::Markdown => result.push_str(&format!("\n<!-- {} -->\n", notice)),
+            OutputFormat::Raw => result.push_str(&format!("// {}\n", notice)),
+            OutputFormat::Jsonl => {}
+        }
+        true
+    }
+
+    /// Options for rendering the collected files as configured
+    fn render_options(&self) -> RenderOptions {
+        RenderOptions {
+            format: self.options.format.clone(),
+            group_by_dir: self.options.group_by_dir,
+            footer_stats: self.options.footer_stats,
+            price_per_1k_tokens: self.options.price_per_1k_tokens,
+            git_info: self.options.git_info.clone(),
+            command: self.options.command.clone(),
+            combine_under: self.options.combine_under,
+            combine_per_dir_under: self.options.combine_per_dir_under,
+            separator: self.options.block_separator.clone(),
+            dedup_blocks: self.options.dedup_blocks,
+            sort: self.options.sort,
+            readme_first: self.options.readme_first,
+            base_indent: self.options.base_indent.clone(),
+        }
+    }
+
+    /// Indices of the files in output order: files to emit in argument order first
+    /// (with `preserve_arg_order`), then all other files
+    fn render_order(&self) -> (Vec<usize>, Vec<usize>) {
+        if !self.options.preserve_arg_order {
+            return (Vec::new(), (0..self.target_files.len()).collect());
+        }
+
+        let explicit: HashSet<usize> = self.explicit_files.iter().copied().collect();
+        let walked = (0..self.target_files.len())
+            .filter(|index| !explicit.contains(index))
+            .collect();
+        (self.explicit_files.clone(), walked)
+    }
+
+    /// Format a single collected file as a markdown code block
+    fn format_block(&self, index: usize) -> String {
+        format::format_block(
+            &self.target_files[index],
+            &self.contents[index],
+            &self.options.format,
+        )
+    }
+
+    /// Number of files the walk visited before the include/exclude patterns and content
+    /// checks were applied. Files in directories pruned by an exclude pattern aren't visited.
+    pub fn candidate_count(&self) -> usize {
+        self.candidates
+    }
+
+    /// Indent every non-empty line of the output, fences included, by `indent`
+    /// (e.g. `"   "` to nest the blocks under a markdown list item)
+    ///
+    /// This also applies to files already processed.
+    pub fn set_base_indent(&mut self, indent: &str) {
+        self.options.base_indent = indent.to_string();
+        self.invalidate();
+    }
+
+    /// Rewrite the whole assembled result with `transform` (e.g. to redact secrets or wrap
+    /// the output in an outer tag)
+    ///
+    /// The transform runs once whenever the result is built, i.e. on the first read after
+    /// files were added, and also applies to files already processed. It runs after the
+    /// output was cut at `max_output_bytes`, so the limit applies to the untransformed
+    /// output: a transform that adds text makes the result longer than the limit.
+    /// Statistics such as [`get_total_tokens`](Self::get_total_tokens) are counted before
+    /// the transform.
+    pub fn set_output_transform(&mut self, transform: Box<dyn Fn(String) -> String + Send + Sync>) {
+        self.options.output_transform = Some(OutputTransform(Arc::from(transform)));
+        self.invalidate();
+    }
+
+    /// Whether processing was stopped by the interrupt flag, so only the files collected
+    /// before it was set are in the result and the statistics
+    pub fn is_interrupted(&self) -> bool {
+        self.options
+            .interrupt
+            .as_ref()
+            .is_some_and(|flag| flag.load(AtomicOrdering::Relaxed))
+    }
+
+    /// Whether the result was cut at the `max_output_bytes` limit
+    ///
+    /// Statistics such as [`get_total_tokens`](Self::get_total_tokens) still cover all files.
+    pub fn is_truncated(&self) -> bool {
+        self.rendered().truncated
     }
 
     /// Get information about all processed files
@@ -203,13 +1535,80 @@ impl FileProcessor {
         &self.target_files
     }
 
+    /// Get each processed file with its own formatted block, in processing order.
+    /// Files past the `max_output_bytes` limit are left out.
+    pub fn get_file_blocks(&self) -> Vec<(&FileInfo, String)> {
+        (0..self.kept_files.unwrap_or(self.target_files.len()))
+            .map(|index| (&self.target_files[index], self.format_block(index)))
+            .collect()
+    }
+
+    /// Get the first `lines` lines of each processed file's content, in processing order.
+    /// Contents past the `max_output_bytes` limit aren't kept, so their previews are empty.
+    pub fn get_previews(&self, lines: usize) -> Vec<(&FileInfo, Vec<&str>)> {
+        self.target_files
+            .iter()
+            .zip(&self.contents)
+            .map(|(file, content)| (file, content.lines().take(lines).collect()))
+            .collect()
+    }
+
+    /// Write each file's block to `<dir>/<relative_path>.md`, creating directories as needed
+    ///
+    /// Paths outside the current directory are written under their normal components only,
+    /// so nothing is written outside `dir`.
+    pub fn write_output_dir(&self, dir: &Path) -> Result<()> {
+        for (file, block) in self.get_file_blocks() {
+            let relative: PathBuf = Path::new(&file.path)
+                .components()
+                .filter(|component| matches!(component, std::path::Component::Normal(_)))
+                .collect();
+            let mut target = dir.join(relative).into_os_string();
+            target.push(".md");
+            let target = PathBuf::from(target);
+
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&target, block)?;
+        }
+        Ok(())
+    }
+
+    /// Get the processed files as HTML, one heading and `<pre>` block per file
+    pub fn get_html(&self) -> String {
+        self.target_files
+            .iter()
+            .zip(&self.contents)
+            .take(self.kept_files.unwrap_or(self.target_files.len()))
+            .map(|(file, content)| format::html_block(file, content))
+            .collect()
+    }
+
+    /// Get the relative paths of all processed files, one per line
+    pub fn get_file_list(&self) -> String {
+        self.target_files
+            .iter()
+            .map(|file| format!("{}\n", file.path))
+            .collect()
+    }
+
+    /// Get information about files that were found but skipped
+    ///
+    /// # Returns
+    ///
+    /// A slice containing the path and skip reason of each skipped file
+    pub fn get_skipped_files(&self) -> &[SkippedFile] {
+        &self.skipped_files
+    }
+
     /// Get the formatted result string containing all file contents
     ///
     /// # Returns
     ///
     /// A string containing all file contents formatted with markdown code blocks
     pub fn get_result(&self) -> &str {
-        &self.result
+        &self.rendered().result
     }
 
     /// Get the total size of all processed files in bytes
@@ -218,7 +1617,7 @@ impl FileProcessor {
     ///
     /// The total size in bytes
     pub fn get_total_size(&self) -> usize {
-        self.result.len()
+        self.rendered().result.len()
     }
 
     /// Get the total number of tokens across all processed files
@@ -230,6 +1629,87 @@ impl FileProcessor {
         self.target_files.iter().map(|f| f.tokens).sum()
     }
 
+    /// Get the `n` largest processed files by size, largest first
+    pub fn top_files_by_size(&self, n: usize) -> Vec<&FileInfo> {
+        self.top_files(n, |file| file.size)
+    }
+
+    /// Get the `n` processed files with the most tokens, largest first
+    pub fn top_files_by_tokens(&self, n: usize) -> Vec<&FileInfo> {
+        self.top_files(n, |file| file.tokens)
+    }
+
+    fn top_files(&self, n: usize, key: impl Fn(&FileInfo) -> usize) -> Vec<&FileInfo> {
+        let mut files: Vec<&FileInfo> = self.target_files.iter().collect();
+        // 同じ値の場合は処理順を保つ
+        files.sort_by_key(|file| std::cmp::Reverse(key(file)));
+        files.truncate(n);
+        files
+    }
+
+    /// Get summary statistics of the processed files
+    ///
+    /// # Returns
+    ///
+    /// The file count, total size, total tokens and per-language file counts
+    pub fn stats(&self) -> Stats {
+        let mut languages = BTreeMap::new();
+        for file in &self.target_files {
+            if let Some(language) = detect_language(Path::new(&file.path)) {
+                *languages.entry(language.to_string()).or_insert(0) += 1;
+            }
+        }
+
+        Stats {
+            files: self.target_files.len(),
+            total_size: self.target_files.iter().map(|f| f.size).sum(),
+            total_tokens: self.get_total_tokens(),
+            languages,
+        }
+    }
+
+    /// Get the tree of processed files, annotated with the tokens of each file and the
+    /// sum of the tokens below each directory
+    pub fn get_cost_tree(&self) -> String {
+        // パス -> (ディレクトリかどうか, トークン数)
+        let mut tree: BTreeMap<PathBuf, (bool, usize)> = BTreeMap::new();
+        for file in &self.target_files {
+            let path = Path::new(&file.path);
+            for dir in path.ancestors().skip(1) {
+                if dir.file_name().is_some() {
+                    tree.entry(dir.to_path_buf()).or_insert((true, 0)).1 += file.tokens;
+                }
+            }
+            tree.insert(path.to_path_buf(), (false, file.tokens));
+        }
+
+        let mut tree: Vec<(PathBuf, (bool, usize))> = tree.into_iter().collect();
+        if self.options.dirs_first {
+            tree.sort_by(|(a, (a_is_dir, _)), (b, (b_is_dir, _))| {
+                dirs_first_cmp(a, *a_is_dir, b, *b_is_dir)
+            });
+        }
+
+        let mut output = String::new();
+        for (path, (is_dir, tokens)) in tree {
+            let depth = path
+                .components()
+                .filter(|component| matches!(component, std::path::Component::Normal(_)))
+                .count();
+            let indent = "  ".repeat(depth.saturating_sub(1));
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let slash = if is_dir { "/" } else { "" };
+            output.push_str(&format!(
+                "{}└── {}{} ({} tokens)\n",
+                indent,
+                name,
+                slash,
+                format_number(tokens)
+            ));
+        }
+        output
+    }
+
     /// Get a string representation of the directory structure
     ///
     /// # Returns
@@ -247,27 +1727,35 @@ impl FileProcessor {
         _depth: usize,
         output: &mut String,
     ) -> Result<()> {
-        let walker = WalkBuilder::new(path)
-            .hidden(false)
-            .git_ignore(true)
-            .git_global(true)
-            .ignore(true)
-            .build();
+        let walker = self.walk_builder(path).build();
 
         // エントリを収集
-        let entries: Vec<_> = walker
+        let mut entries: Vec<_> = walker
             .filter_map(Result::ok)
             .filter(|entry| {
                 let path = entry.path();
-                !path.to_string_lossy().contains("/.git/")
+                entry.depth() > 0
+                    && !path.to_string_lossy().contains("/.git/")
                     && path
                         .file_name()
                         .and_then(|n| n.to_str())
                         .map(|n| n != ".git" && n != ".gitignore")
                         .unwrap_or(false)
             })
+            .take(self.options.max_entries.map_or(usize::MAX, |max| max + 1))
             .collect();
 
+        // 上限を超えた場合は打ち切る
+        let truncated = self.options.max_entries.filter(|&max| entries.len() > max);
+        if let Some(max) = truncated {
+            log::warn!(
+                "Directory structure truncated after {} entries under {}",
+                max,
+                path.display()
+            );
+            entries.truncate(max);
+        }
+
         // ディレクトリ構造をツリー形式で構築
         let mut tree: std::collections::BTreeMap<PathBuf, bool> = std::collections::BTreeMap::new();
 
@@ -283,7 +1771,7 @@ impl FileProcessor {
                     current.push(component);
                     if !tree.contains_key(&current) {
                         let is_dir = if current == entry.path().strip_prefix(path).unwrap() {
-                            entry.file_type().map_or(false, |ft| ft.is_dir())
+                            entry.file_type().is_some_and(|ft| ft.is_dir())
                         } else {
                             true
                         };
@@ -293,8 +1781,23 @@ impl FileProcessor {
             }
         }
 
+        // ファイルを含むディレクトリを記録
+        let mut non_empty_dirs: HashSet<PathBuf> = HashSet::new();
+        for (path, _) in tree.iter().filter(|(_, is_dir)| !**is_dir) {
+            non_empty_dirs.extend(path.ancestors().skip(1).map(Path::to_path_buf));
+        }
+
+        let mut tree: Vec<(PathBuf, bool)> = tree.into_iter().collect();
+        if self.options.dirs_first {
+            tree.sort_by(|(a, a_is_dir), (b, b_is_dir)| dirs_first_cmp(a, *a_is_dir, b, *b_is_dir));
+        }
+
         // ツリーを表示
         for (path, is_dir) in tree {
+            if is_dir && !self.options.show_empty_dirs && !non_empty_dirs.contains(&path) {
+                continue;
+            }
+
             let depth = path.components().count();
             let indent = "  ".repeat(depth.saturating_sub(1));
             let name = path.file_name().unwrap_or_default().to_string_lossy();
@@ -306,10 +1809,266 @@ impl FileProcessor {
             }
         }
 
+        if let Some(max) = truncated {
+            output.push_str(&format!("└── ... (truncated after {} entries)\n", max));
+        }
+
         Ok(())
     }
 }
 
+/// Cheap token estimate from the size alone (about 4 bytes per token), for files
+/// excluded from full counting by `count_extensions`
+/// Canonicalize `path` even if it doesn't exist yet, by canonicalizing its parent
+/// directory instead; falls back to `path` itself when that fails too
+pub(crate) fn canonicalize_lenient(path: &Path) -> PathBuf {
+    if let Ok(canonical) = path.canonicalize() {
+        return canonical;
+    }
+    match (
+        path.parent().and_then(|parent| parent.canonicalize().ok()),
+        path.file_name(),
+    ) {
+        (Some(parent), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn quick_token_estimate(content: &str) -> usize {
+    content.len().div_ceil(4)
+}
+
+/// Read everything from `reader`, or `None` as soon as more than `limit` bytes were read.
+///
+/// The limit is checked on the bytes actually read rather than on the size reported before
+/// reading, so a file growing while it's read is still caught. An error partway through
+/// is returned as is, never as partial content.
+pub(crate) fn read_limited(
+    mut reader: impl Read,
+    limit: Option<usize>,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut bytes = Vec::new();
+    match limit {
+        Some(limit) => {
+            reader.take(limit as u64 + 1).read_to_end(&mut bytes)?;
+            if bytes.len() > limit {
+                return Ok(None);
+            }
+        }
+        None => {
+            reader.read_to_end(&mut bytes)?;
+        }
+    }
+    Ok(Some(bytes))
+}
+
+/// Whether the file at `path` was modified less than `window` ago. Files whose
+/// modification time can't be read, or lies in the future, are kept.
+fn modified_within(path: &Path, window: Duration) -> bool {
+    match fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified.elapsed().map_or(true, |age| age <= window),
+        Err(_) => true,
+    }
+}
+
+/// Whether the extension of `path` is one of `extensions`
+fn has_extension_in(path: &Path, extensions: &HashSet<String>) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.contains(ext))
+}
+
+/// Estimate the number of tokens in a string
+pub(crate) fn estimate_tokens(content: &str) -> usize {
+    content
+        .split(|c: char| {
+            c.is_whitespace()
+                || c.is_ascii_punctuation()
+                || matches!(c, '(' | ')' | '[' | ']' | '{' | '}')
+                || matches!(
+                    c,
+                    '+' | '-'
+                        | '*'
+                        | '/'
+                        | '='
+                        | '<'
+                        | '>'
+                        | '&'
+                        | '|'
+                        | '!'
+                        | '@'
+                        | '#'
+                        | '$'
+                        | '%'
+                        | '^'
+                )
+        })
+        .filter(|s| !s.is_empty())
+        .count()
+}
+
+/// Parse comma-separated glob patterns, dropping duplicates while keeping their order.
+/// Whitespace around each pattern is trimmed and empty segments are skipped.
+fn parse_patterns(patterns: &Option<String>) -> Result<Vec<Pattern>> {
+    let mut parsed: Vec<Pattern> = Vec::new();
+    let patterns = patterns
+        .iter()
+        .flat_map(|patterns| patterns.split(','))
+        .map(str::trim)
+        .filter(|pattern| !pattern.is_empty());
+    for pattern in patterns {
+        let pattern = Pattern::new(pattern).map_err(CflError::from)?;
+        if !parsed.contains(&pattern) {
+            parsed.push(pattern);
+        }
+    }
+    Ok(parsed)
+}
+
+/// Read the `*.include` and `*.ignore` files in `dir`, one gitignore-style glob per line
+/// with `#` comments, unioning the patterns of all files of each kind.
+///
+/// # Returns
+///
+/// The include and the exclude matcher, each `None` when no file of its kind has patterns
+fn load_pattern_dir(dir: &Path) -> Result<(Option<Override>, Option<Override>)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok((None, None));
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+
+    let root = dir.parent().unwrap_or(dir);
+    let mut includes = OverrideBuilder::new(root);
+    let mut excludes = OverrideBuilder::new(root);
+    for file in files {
+        // 上書き設定では `!` 付きのグロブが除外になる
+        let (builder, prefix) = match file.extension().and_then(|ext| ext.to_str()) {
+            Some("include") => (&mut includes, ""),
+            Some("ignore") => (&mut excludes, "!"),
+            _ => continue,
+        };
+        log::debug!("Reading patterns from {}", file.display());
+        let patterns = fs::read_to_string(&file)?;
+        let patterns = patterns
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        for pattern in patterns {
+            builder.add(&format!("{}{}", prefix, pattern))?;
+        }
+    }
+
+    let build = |builder: OverrideBuilder| -> Result<Option<Override>> {
+        let matcher = builder.build()?;
+        Ok((!matcher.is_empty()).then_some(matcher))
+    };
+    Ok((build(includes)?, build(excludes)?))
+}
+
+/// Check whether any of the patterns matches the file.
+///
+/// Patterns starting with `/` are matched both against the absolute path of the file
+/// (e.g. `/abs/vendor/**`) and, anchored like in `.gitignore`, against its relative
+/// path (e.g. `/src/*.rs`). Other patterns containing a `/` are matched against the
+/// relative path, and all remaining patterns against the file name.
+fn matches_any(patterns: &[Pattern], path: &Path, relative_path: &str) -> bool {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    patterns.iter().any(|pattern| {
+        if Path::new(pattern.as_str()).is_absolute() {
+            std::path::absolute(path).is_ok_and(|absolute| pattern.matches_path(&absolute))
+                || pattern.matches(&format!("/{}", relative_path))
+        } else if pattern.as_str().contains('/') {
+            pattern.matches(relative_path)
+        } else {
+            pattern.matches(file_name)
+        }
+    })
+}
+
+/// Whether a directory containing `relative_path` matches one of the patterns
+fn in_excluded_dir(patterns: &[Pattern], relative_path: &str) -> bool {
+    Path::new(relative_path)
+        .ancestors()
+        .skip(1)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .any(|dir| matches_any(patterns, dir, &dir.to_string_lossy()))
+}
+
+/// Decides which directories the walk skips entirely because an exclude pattern
+/// matches the directory itself
+struct ExcludedDirs {
+    exclude_patterns: Vec<Pattern>,
+    exception_patterns: Vec<Pattern>,
+    current_dir: PathBuf,
+}
+
+impl ExcludedDirs {
+    /// Whether `dir` matches an exclude pattern and no exception pattern could match
+    /// a file below it
+    fn prunes(&self, dir: &Path) -> bool {
+        let relative = dir
+            .strip_prefix(&self.current_dir)
+            .unwrap_or(dir)
+            .to_string_lossy();
+        let relative = relative.trim_end_matches('/');
+        matches_any(&self.exclude_patterns, dir, relative)
+            && !self
+                .exception_patterns
+                .iter()
+                .any(|pattern| may_match_below(pattern, relative))
+    }
+}
+
+/// Whether `pattern` could match a path below the directory `relative_dir`, judged
+/// by its literal prefix before the first wildcard
+fn may_match_below(pattern: &Pattern, relative_dir: &str) -> bool {
+    let literal = pattern
+        .as_str()
+        .split(['*', '?', '['])
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches('/');
+    let dir = format!("{}/", relative_dir);
+    literal.starts_with(&dir) || dir.starts_with(literal)
+}
+
+/// Nearest ancestor of `dir` (or `dir` itself) containing `.git`
+fn find_repo_root(dir: &Path) -> Option<PathBuf> {
+    let dir = std::path::absolute(dir).ok()?;
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Order tree entries so that, among siblings, directories come before files.
+/// Parents still precede their children, and siblings of the same kind sort by name.
+fn dirs_first_cmp(a: &Path, a_is_dir: bool, b: &Path, b_is_dir: bool) -> Ordering {
+    let a_components: Vec<_> = a.components().collect();
+    let b_components: Vec<_> = b.components().collect();
+
+    // 最初に異なる要素で比較する
+    match a_components
+        .iter()
+        .zip(&b_components)
+        .position(|(x, y)| x != y)
+    {
+        Some(index) => {
+            // 途中の要素は必ずディレクトリ
+            let a_dir = index + 1 < a_components.len() || a_is_dir;
+            let b_dir = index + 1 < b_components.len() || b_is_dir;
+            b_dir
+                .cmp(&a_dir)
+                .then_with(|| a_components[index].cmp(&b_components[index]))
+        }
+        None => a_components.len().cmp(&b_components.len()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,44 @@
+use crate::clipboard::Clipboard;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// A destination the result is written to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Sink {
+    /// A file, overwritten with the result
+    File(PathBuf),
+    /// The clipboard
+    Clipboard,
+    /// Standard output
+    Stdout,
+}
+
+/// Write `text` to each of `sinks` in order, stopping at the first failure.
+///
+/// When `html` is given, the clipboard receives it as the HTML flavor with `text` as the
+/// plain-text flavor.
+pub fn deliver(
+    text: &str,
+    html: Option<&str>,
+    sinks: &[Sink],
+    stdout: &mut dyn Write,
+    clipboard: &mut dyn Clipboard,
+) -> Result<()> {
+    for sink in sinks {
+        match sink {
+            Sink::File(path) => fs::write(path, text)
+                .with_context(|| format!("Failed to write {}", path.display()))?,
+            Sink::Clipboard => match html {
+                Some(html) => clipboard.set_html(html, text)?,
+                None => clipboard.set_text(text)?,
+            },
+            Sink::Stdout => {
+                stdout.write_all(text.as_bytes())?;
+                stdout.flush()?;
+            }
+        }
+    }
+    Ok(())
+}
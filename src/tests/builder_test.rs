@@ -1,5 +1,5 @@
 // src/tests/builder_test.rs
-use cfl::CflBuilder;
+use crate::{CflBuilder, OutputFormat, SortKey};
 use std::fs;
 use tempfile::TempDir;
 
@@ -79,4 +79,1536 @@ fn test_builder_directory_structure() {
     assert!(structure.contains("src/"));
     assert!(structure.contains("tests/"));
     assert!(structure.contains("Cargo.toml"));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_builder_group_by_dir() {
+    let temp_dir = setup_test_directory();
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.rs")
+        .group_by_dir(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+
+    processor.process_path(temp_dir.path()).unwrap();
+    let result = processor.get_result();
+
+    let src_heading = result.find("## src/").expect("missing src/ heading");
+    let tests_heading = result.find("## tests/").expect("missing tests/ heading");
+    assert!(src_heading < tests_heading);
+    assert_eq!(result.matches("## ").count(), 2);
+
+    let main_block = result.find("```src/main.rs").unwrap();
+    let test_block = result.find("```tests/test.rs").unwrap();
+    assert!(src_heading < main_block && main_block < tests_heading);
+    assert!(tests_heading < test_block);
+}
+
+#[test]
+fn test_builder_editorconfig_trims_trailing_whitespace() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join(".editorconfig"),
+        "root = true\n\n[*]\ntrim_trailing_whitespace = true\n"
+    ).unwrap();
+    fs::write(
+        temp_dir.path().join("main.rs"),
+        "fn main() {   \n    println!(\"Hello\");\t\n}\n"
+    ).unwrap();
+
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.rs")
+        .editorconfig(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    assert!(processor
+        .get_result()
+        .contains("fn main() {\n    println!(\"Hello\");\n}\n"));
+    assert!(!processor.get_result().contains("   \n"));
+
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.rs")
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    assert!(processor.get_result().contains("fn main() {   \n"));
+}
+
+#[test]
+fn test_builder_collapse_imports() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(
+        temp_dir.path().join("main.rs"),
+        "use std::fs;\nuse std::path::{\n    Path,\n    PathBuf,\n};\n\nuse crate::error::CflError;\n\nfn main() {\n    let user = \"use this\";\n}\n"
+    ).unwrap();
+
+    let mut processor = CflBuilder::new()
+        .collapse_imports(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    let result = processor.get_result();
+
+    assert!(result.contains("```main.rs\n// 3 imports collapsed\n\nfn main() {\n"));
+    assert!(!result.contains("use std::fs;"));
+    assert!(result.contains("    let user = \"use this\";\n"));
+}
+
+#[test]
+fn test_builder_respect_binary_gitattributes() {
+    let temp_dir = setup_test_directory();
+    fs::create_dir(temp_dir.path().join(".git")).unwrap();
+    fs::write(temp_dir.path().join(".gitattributes"), "*.dat binary\n").unwrap();
+    fs::write(temp_dir.path().join("src/data.dat"), "looks like plain text").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .respect_binary_gitattributes(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    assert!(!processor.get_target_files().iter().any(|f| f.path.ends_with("data.dat")));
+    assert!(processor.get_skipped_files().iter().any(|f| f.path == "src/data.dat"));
+    assert!(processor.get_target_files().iter().any(|f| f.path.ends_with("main.rs")));
+
+    let mut processor = CflBuilder::new()
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    assert!(processor.get_target_files().iter().any(|f| f.path.ends_with("data.dat")));
+}
+
+#[test]
+fn test_builder_show_empty_dirs() {
+    let temp_dir = setup_test_directory();
+    fs::create_dir(temp_dir.path().join("docs")).unwrap();
+
+    let processor = CflBuilder::new()
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    let structure = processor.get_directory_structure().unwrap();
+    assert!(!structure.contains("docs/"));
+    assert!(structure.contains("src/"));
+
+    let processor = CflBuilder::new()
+        .show_empty_dirs(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    let structure = processor.get_directory_structure().unwrap();
+    assert!(structure.contains("docs/"));
+}
+
+#[test]
+fn test_builder_raw_output() {
+    let temp_dir = setup_test_directory();
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.rs")
+        .output_format(OutputFormat::Raw)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+
+    processor.process_path(temp_dir.path()).unwrap();
+    let result = processor.get_result();
+
+    assert!(!result.contains('`'));
+    for (path, content) in [
+        ("src/main.rs", "fn main() { println!(\"Hello\"); }"),
+        ("src/lib.rs", "pub fn add(a: i32, b: i32) -> i32 { a + b }"),
+        ("tests/test.rs", "#[test] fn test_add() { assert_eq!(2 + 2, 4); }"),
+    ] {
+        let banner = format!("// ==== {} ====\n", path);
+        assert!(result.contains(&format!("{}{}\n\n", banner, content)));
+    }
+}
+
+#[test]
+fn test_builder_footer_stats() {
+    let temp_dir = setup_test_directory();
+    let mut processor = CflBuilder::new()
+        .footer_stats(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+
+    processor.process_path(temp_dir.path()).unwrap();
+    let result = processor.get_result();
+    let stats = processor.stats();
+
+    assert_eq!(stats.files, 4);
+    assert_eq!(stats.languages.get("rust"), Some(&3));
+    assert_eq!(stats.languages.get("toml"), Some(&1));
+
+    let footer = format!(
+        "<!-- Total: 4 files, {} tokens, languages: rust, toml -->\n",
+        processor.get_total_tokens()
+    );
+    assert!(result.ends_with(&footer));
+    let last_block_end = result.rfind("```\n").unwrap();
+    assert!(last_block_end < result.rfind(&footer).unwrap());
+}
+
+#[test]
+fn test_builder_price_per_1k_tokens() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("words.txt"), "word ".repeat(2_500)).unwrap();
+
+    let mut processor = CflBuilder::new()
+        .footer_stats(true)
+        .price_per_1k_tokens(0.015)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // 2,500 トークン / 1000 * $0.015 = $0.0375
+    assert_eq!(processor.get_total_tokens(), 2_500);
+    assert_eq!(crate::format::format_cost(processor.get_total_tokens(), 0.015), "$0.0375");
+    assert!(processor
+        .get_result()
+        .ends_with("<!-- Total: 1 files, 2,500 tokens, languages: text, estimated cost: $0.0375 -->\n"));
+}
+
+#[test]
+fn test_builder_max_size_per_ext() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("data.json"), format!("[{}]", "1,".repeat(10_000))).unwrap();
+    fs::write(temp_dir.path().join("big.rs"), "// filler\n".repeat(2_000)).unwrap();
+    fs::write(temp_dir.path().join("small.json"), "{}").unwrap();
+
+    let limits = crate::cli::parse_size_limits("json=1k").unwrap();
+    let mut processor = CflBuilder::new()
+        .max_size_per_ext(limits)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let files = processor.get_target_files();
+    assert!(!files.iter().any(|f| f.path == "data.json"));
+    assert!(files.iter().any(|f| f.path == "small.json"));
+    assert!(files.iter().any(|f| f.path == "big.rs"));
+    assert!(processor
+        .get_skipped_files()
+        .iter()
+        .any(|f| f.path == "data.json" && f.reason == crate::SkipReason::TooLarge));
+
+    // 拡張子ごとの上限がない場合はグローバル上限が適用される
+    let mut processor = CflBuilder::new()
+        .max_size(1024)
+        .max_size_per_ext(crate::cli::parse_size_limits("rs=1m").unwrap())
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let files = processor.get_target_files();
+    assert!(!files.iter().any(|f| f.path == "data.json"));
+    assert!(files.iter().any(|f| f.path == "big.rs"));
+}
+
+#[test]
+fn test_builder_max_line_length() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("bundle.min.js"), "var a=1;".repeat(1_000)).unwrap();
+    fs::write(temp_dir.path().join("app.js"), "const a = 1;\n").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .max_line_length(100)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let result = processor.get_result();
+    assert!(result.contains(&format!("{} [... 7900 chars truncated]", "var a=1;".repeat(12) + "var ")));
+    assert!(result.contains("const a = 1;\n"));
+
+    let mut processor = CflBuilder::new()
+        .max_line_length(100)
+        .long_line_action(crate::LongLineAction::Skip)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let files = processor.get_target_files();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].path, "app.js");
+    assert!(processor
+        .get_skipped_files()
+        .iter()
+        .any(|f| f.path == "bundle.min.js" && f.reason == crate::SkipReason::Minified));
+}
+
+#[test]
+fn test_builder_dedup() {
+    let temp_dir = setup_test_directory();
+    let main_rs = temp_dir.path().join("src/main.rs");
+
+    let mut processor = CflBuilder::new()
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(&main_rs).unwrap();
+    processor.process_path(&main_rs).unwrap();
+    assert_eq!(processor.get_target_files().len(), 1);
+
+    let mut processor = CflBuilder::new()
+        .dedup(false)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(&main_rs).unwrap();
+    processor.process_path(&main_rs).unwrap();
+
+    assert_eq!(processor.get_target_files().len(), 2);
+    assert_eq!(processor.get_result().matches("```src/main.rs\n").count(), 2);
+}
+
+#[test]
+fn test_builder_block_separator() {
+    let temp_dir = setup_test_directory();
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.rs")
+        .block_separator("\n---\n\n")
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(&temp_dir.path().join("src")).unwrap();
+
+    let result = processor.get_result();
+    assert_eq!(result.matches("---").count(), 1);
+    assert!(result.starts_with("```src/"));
+    assert!(result.ends_with("\n```\n"));
+    assert!(result.contains("\n```\n\n---\n\n```src/"));
+
+    // デフォルトでは空行で区切る
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.rs")
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(&temp_dir.path().join("src")).unwrap();
+
+    let result = processor.get_result();
+    assert!(result.contains("\n```\n\n```src/"));
+    assert!(result.ends_with("\n```\n"));
+}
+
+#[test]
+fn test_builder_dirs_first() {
+    let temp_dir = setup_test_directory();
+    fs::write(temp_dir.path().join("build.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("src/a.rs"), "").unwrap();
+    fs::create_dir(temp_dir.path().join("src/z")).unwrap();
+    fs::write(temp_dir.path().join("src/z/mod.rs"), "").unwrap();
+
+    let processor = CflBuilder::new()
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    let structure = processor.get_directory_structure().unwrap();
+    let lines: Vec<&str> = structure.lines().collect();
+    assert_eq!(lines[0], "└── Cargo.toml");
+
+    let processor = CflBuilder::new()
+        .dirs_first(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    let structure = processor.get_directory_structure().unwrap();
+    let lines: Vec<&str> = structure.lines().collect();
+
+    assert_eq!(
+        lines,
+        vec![
+            "└── src/",
+            "  └── z/",
+            "    └── mod.rs",
+            "  └── a.rs",
+            "  └── lib.rs",
+            "  └── main.rs",
+            "└── tests/",
+            "  └── test.rs",
+            "└── Cargo.toml",
+            "└── build.rs",
+        ]
+    );
+}
+
+#[cfg(feature = "git")]
+#[test]
+fn test_builder_include_tracked_ignored() {
+    let temp_dir = setup_test_directory();
+    fs::write(temp_dir.path().join(".gitignore"), "*.toml\n").unwrap();
+    fs::write(temp_dir.path().join("local.toml"), "ignored = true\n").unwrap();
+
+    // Cargo.tomlだけを強制的に追跡させる
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .arg("-C")
+            .arg(temp_dir.path())
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    assert!(git(&["init", "-q"]).status.success());
+    assert!(git(&["add", "-f", "Cargo.toml"]).status.success());
+
+    let mut processor = CflBuilder::new()
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    assert!(!processor.get_target_files().iter().any(|f| f.path == "Cargo.toml"));
+
+    let mut processor = CflBuilder::new()
+        .include_tracked_ignored(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    let files = processor.get_target_files();
+
+    assert_eq!(files.iter().filter(|f| f.path == "Cargo.toml").count(), 1);
+    assert!(!files.iter().any(|f| f.path == "local.toml"));
+    assert!(files.iter().any(|f| f.path == "src/main.rs"));
+}
+
+#[test]
+fn test_builder_git_info_header() {
+    let temp_dir = setup_test_directory();
+    let info = crate::GitInfo {
+        branch: Some("main".to_string()),
+        commit: "1a2b3c4".to_string(),
+    };
+
+    let mut processor = CflBuilder::new()
+        .include_patterns("main.rs")
+        .git_info(Some(info.clone()))
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    assert!(processor
+        .get_result()
+        .starts_with("<!-- Generated from main@1a2b3c4 -->\n\n```src/main.rs\n"));
+
+    let mut processor = CflBuilder::new()
+        .include_patterns("main.rs")
+        .output_format(OutputFormat::Raw)
+        .git_info(Some(crate::GitInfo { branch: None, ..info }))
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    assert!(processor
+        .get_result()
+        .starts_with("// Generated from 1a2b3c4\n\n// ==== src/main.rs ====\n"));
+
+    // リポジトリ外ではヘッダーを出力しない
+    let mut processor = CflBuilder::new()
+        .include_patterns("main.rs")
+        .git_info(None)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    assert!(processor.get_result().starts_with("```src/main.rs\n"));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_builder_on_error() {
+    let temp_dir = setup_test_directory();
+    // /proc/self/mem は先頭からの読み込みがI/Oエラーになる
+    let unreadable = std::path::Path::new("/proc/self/mem");
+
+    let build = |policy| {
+        CflBuilder::new()
+            .on_error(policy)
+            .current_dir(temp_dir.path())
+            .build()
+            .unwrap()
+    };
+
+    let mut processor = build(crate::OnError::Abort);
+    let err = processor.process_path(unreadable).unwrap_err();
+    assert!(err.to_string().contains("Failed to read file: /proc/self/mem"));
+
+    for policy in [crate::OnError::Skip, crate::OnError::Warn] {
+        let mut processor = build(policy);
+        processor.process_path(unreadable).unwrap();
+        processor.process_path(temp_dir.path()).unwrap();
+
+        assert_eq!(processor.get_target_files().len(), 4);
+        let skipped = processor.get_skipped_files();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].reason, crate::SkipReason::Unreadable);
+    }
+}
+
+#[test]
+fn test_builder_per_file_max_tokens() {
+    let temp_dir = setup_test_directory();
+    let large: String = (0..100).map(|i| format!("let value_{} = {};\n", i, i)).collect();
+    fs::write(temp_dir.path().join("src/large.rs"), &large).unwrap();
+
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.rs")
+        .per_file_max_tokens(10)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let result = processor.get_result();
+    assert!(result.contains(
+        "```src/large.rs\nlet value_0 = 0;\nlet value_1 = 1;\n[... 98 more lines truncated]\n```"
+    ));
+    // 上限内のファイルはそのまま
+    assert!(result.contains("```src/main.rs\nfn main() { println!(\"Hello\"); }\n```"));
+
+    let large_file = processor
+        .get_target_files()
+        .iter()
+        .find(|f| f.path == "src/large.rs")
+        .unwrap();
+    assert!(large_file.size < large.len());
+    assert!(large_file.tokens <= 15);
+}
+
+#[test]
+fn test_builder_preserve_arg_order() {
+    let temp_dir = setup_test_directory();
+    let paths = ["tests/test.rs", "src/main.rs", "Cargo.toml"];
+
+    let mut processor = CflBuilder::new()
+        .group_by_dir(true)
+        .preserve_arg_order(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    for path in paths {
+        processor.process_path(&temp_dir.path().join(path)).unwrap();
+    }
+
+    let result = processor.get_result();
+    let positions: Vec<usize> = paths
+        .iter()
+        .map(|path| result.find(&format!("```{}\n", path)).unwrap())
+        .collect();
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(!result.contains("## "));
+    assert!(result.ends_with("\n```\n"));
+
+    // 指定しない場合はディレクトリごとにまとめられる
+    let mut processor = CflBuilder::new()
+        .group_by_dir(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    for path in paths {
+        processor.process_path(&temp_dir.path().join(path)).unwrap();
+    }
+    assert!(processor.get_result().starts_with("## ./\n\n```Cargo.toml\n"));
+}
+
+#[test]
+fn test_builder_skip_empty() {
+    let temp_dir = setup_test_directory();
+    fs::write(temp_dir.path().join("src/empty.rs"), "").unwrap();
+    fs::write(temp_dir.path().join("src/blank.rs"), "  \n\t\n").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.rs")
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    let files = processor.get_target_files();
+    assert!(files.iter().any(|f| f.path == "src/empty.rs"));
+    assert!(files.iter().any(|f| f.path == "src/blank.rs"));
+
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.rs")
+        .skip_empty(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    assert_eq!(processor.get_target_files().len(), 3);
+    let mut skipped: Vec<_> = processor
+        .get_skipped_files()
+        .iter()
+        .map(|f| (f.path.as_str(), f.reason))
+        .collect();
+    skipped.sort_by_key(|(path, _)| *path);
+    assert_eq!(
+        skipped,
+        vec![
+            ("src/blank.rs", crate::SkipReason::Empty),
+            ("src/empty.rs", crate::SkipReason::Empty),
+        ]
+    );
+}
+
+#[test]
+fn test_builder_fallback_encoding() {
+    let temp_dir = setup_test_directory();
+    let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode("// こんにちは、世界\nfn main() {}\n");
+    fs::write(temp_dir.path().join("src/legacy.rs"), &sjis).unwrap();
+
+    let mut processor = CflBuilder::new()
+        .include_patterns("legacy.rs")
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    assert!(processor.get_target_files().is_empty());
+    assert_eq!(processor.get_skipped_files()[0].reason, crate::SkipReason::Binary);
+
+    let mut processor = CflBuilder::new()
+        .include_patterns("legacy.rs")
+        .fallback_encoding(Some("shift_jis".to_string()))
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    assert_eq!(
+        processor.get_result(),
+        "```src/legacy.rs\n// こんにちは、世界\nfn main() {}\n```\n"
+    );
+
+    let err = CflBuilder::new()
+        .fallback_encoding(Some("no-such-encoding".to_string()))
+        .build()
+        .unwrap_err();
+    assert!(err.to_string().contains("Unknown encoding: no-such-encoding"));
+}
+
+#[test]
+fn test_builder_path_rewrites() {
+    let temp_dir = setup_test_directory();
+    fs::create_dir(temp_dir.path().join("internal")).unwrap();
+    fs::write(temp_dir.path().join("internal/secret_module.rs"), "pub fn hidden() {}").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .include_patterns("internal/*.rs,main.rs")
+        .path_rewrites(vec![
+            ("internal/secret_module.rs".to_string(), "module_a.rs".to_string()),
+            ("src".to_string(), "app".to_string()),
+        ])
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let result = processor.get_result();
+    assert!(result.contains("```module_a.rs\npub fn hidden() {}\n```"));
+    assert!(result.contains("```app/main.rs\n"));
+    assert!(!result.contains("secret_module"));
+    assert!(!result.contains("src/"));
+
+    let paths: Vec<&str> = processor.get_target_files().iter().map(|f| f.path.as_str()).collect();
+    assert!(paths.contains(&"module_a.rs"));
+    assert!(paths.contains(&"app/main.rs"));
+}
+
+#[test]
+fn test_builder_max_entries() {
+    let temp_dir = TempDir::new().unwrap();
+    for i in 0..50 {
+        fs::write(temp_dir.path().join(format!("file_{:02}.rs", i)), "").unwrap();
+    }
+
+    let processor = CflBuilder::new()
+        .max_entries(10)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    let structure = processor.get_directory_structure().unwrap();
+    let lines: Vec<&str> = structure.lines().collect();
+
+    assert_eq!(lines.len(), 11);
+    assert_eq!(lines[10], "└── ... (truncated after 10 entries)");
+
+    // 上限内なら打ち切らない
+    let processor = CflBuilder::new()
+        .max_entries(50)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    let structure = processor.get_directory_structure().unwrap();
+    assert_eq!(structure.lines().count(), 50);
+    assert!(!structure.contains("truncated"));
+}
+
+#[test]
+fn test_builder_combine_under() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("a.toml"), "a = 1\n").unwrap();
+    fs::write(temp_dir.path().join("b.toml"), "b = 2").unwrap();
+    fs::write(temp_dir.path().join("c.toml"), "c = 3\n").unwrap();
+    fs::write(temp_dir.path().join("large.rs"), "// large\n".repeat(100)).unwrap();
+
+    let mut processor = CflBuilder::new()
+        .combine_under(100)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    for name in ["a.toml", "large.rs", "b.toml", "c.toml"] {
+        processor.process_path(&temp_dir.path().join(name)).unwrap();
+    }
+
+    let result = processor.get_result();
+    assert_eq!(
+        result,
+        format!(
+            "```\n// a.toml\na = 1\n// b.toml\nb = 2\n// c.toml\nc = 3\n```\n\n```large.rs\n{}```\n",
+            "// large\n".repeat(100)
+        )
+    );
+    assert_eq!(result.matches("```").count(), 4);
+}
+
+#[test]
+fn test_builder_combine_per_dir_under() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir_all(temp_dir.path().join("src").join("utils")).unwrap();
+    fs::write(temp_dir.path().join("src").join("utils").join("a.rs"), "pub fn a() {}\n").unwrap();
+    fs::write(temp_dir.path().join("src").join("utils").join("b.rs"), "pub fn b() {}").unwrap();
+    fs::write(temp_dir.path().join("src").join("main.rs"), "fn main() {}\n").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .combine_per_dir_under(100)
+        .sort(SortKey::Path)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // 同じディレクトリの小さなファイルは1ブロックに、別ディレクトリのファイルは単独のブロックになる
+    assert_eq!(
+        processor.get_result(),
+        "```src/main.rs\nfn main() {}\n```\n\n```src/utils/\n// a.rs\npub fn a() {}\n// b.rs\npub fn b() {}\n```\n"
+    );
+}
+
+#[test]
+fn test_builder_project_relative() {
+    let temp_dir = TempDir::new().unwrap();
+    let web = temp_dir.path().join("packages").join("web");
+    let core = temp_dir.path().join("crates").join("core");
+    fs::create_dir_all(web.join("src")).unwrap();
+    fs::create_dir_all(core.join("src")).unwrap();
+    fs::write(web.join("package.json"), "{}").unwrap();
+    fs::write(web.join("src").join("app.ts"), "export {};").unwrap();
+    fs::write(core.join("Cargo.toml"), "[package]").unwrap();
+    fs::write(core.join("src").join("lib.rs"), "pub fn core() {}").unwrap();
+    fs::write(temp_dir.path().join("notes.md"), "# Notes").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.ts,*.rs,*.md")
+        .project_relative(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let mut paths: Vec<&str> = processor.get_target_files().iter().map(|f| f.path.as_str()).collect();
+    paths.sort();
+    // マニフェストのないファイルは通常の相対パスのまま
+    assert_eq!(paths, vec!["notes.md", "src/app.ts", "src/lib.rs"]);
+    assert!(processor.get_result().contains("```src/app.ts\nexport {};\n```"));
+}
+
+#[test]
+fn test_builder_include_names() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("Makefile"), "all:\n\tcargo build\n").unwrap();
+    fs::write(temp_dir.path().join("Dockerfile"), "FROM rust").unwrap();
+    fs::write(temp_dir.path().join("notes.txt"), "notes").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.rs")
+        .include_names("Makefile, LICENSE")
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let mut paths: Vec<&str> = processor.get_target_files().iter().map(|f| f.path.as_str()).collect();
+    paths.sort();
+    assert_eq!(paths, vec!["Makefile", "main.rs"]);
+
+    // 除外パターンは名前指定より優先される
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.rs")
+        .exclude_patterns("Makefile")
+        .include_names("Makefile")
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    assert_eq!(processor.get_target_files().len(), 1);
+}
+
+#[test]
+fn test_builder_max_output_bytes() {
+    let temp_dir = TempDir::new().unwrap();
+    for i in 0..10 {
+        fs::write(temp_dir.path().join(format!("file_{}.txt", i)), "x".repeat(100)).unwrap();
+    }
+
+    let mut processor = CflBuilder::new()
+        .max_output_bytes(350)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let result = processor.get_result();
+    let notice = "\n<!-- Output truncated at 350 bytes; 10 files were counted -->\n";
+    assert!(processor.is_truncated());
+    assert!(result.ends_with(notice));
+    assert!(result.len() <= 350 + notice.len() + 1);
+    // 統計はすべてのファイルを対象にする
+    assert_eq!(processor.get_target_files().len(), 10);
+    assert_eq!(processor.stats().total_size, 1000);
+
+    // 上限内なら切り詰めない
+    let mut processor = CflBuilder::new()
+        .max_output_bytes(10_000)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    assert!(!processor.is_truncated());
+    assert!(!processor.get_result().contains("truncated"));
+
+    // 上限は変換前の出力に適用され、変換で追加した部分は切り詰めない
+    let mut processor = CflBuilder::new()
+        .max_output_bytes(350)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.set_output_transform(Box::new(|output| format!("<context>\n{}</context>\n", output)));
+    processor.process_path(temp_dir.path()).unwrap();
+    assert!(processor.is_truncated());
+    assert!(processor.get_result().starts_with("<context>\n"));
+    assert!(processor.get_result().ends_with(&format!("{}</context>\n", notice)));
+}
+
+#[cfg(feature = "git")]
+#[test]
+fn test_builder_process_ref() {
+    let temp_dir = TempDir::new().unwrap();
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .arg("-C")
+            .arg(temp_dir.path())
+            .args(["-c", "user.name=cfl", "-c", "user.email=cfl@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    fs::create_dir(temp_dir.path().join("src")).unwrap();
+    fs::write(temp_dir.path().join("src").join("main.rs"), "fn main() {}\n").unwrap();
+    fs::write(temp_dir.path().join("src").join("lib.rs"), "pub fn v1() {}\n").unwrap();
+    fs::write(temp_dir.path().join(".gitignore"), "*.rs\n").unwrap();
+    assert!(git(&["init", "-q"]).status.success());
+    assert!(git(&["add", "-f", "."]).status.success());
+    assert!(git(&["commit", "-q", "-m", "v1"]).status.success());
+
+    // 作業ツリーの変更はコミットの内容に影響しない
+    fs::write(temp_dir.path().join("src").join("lib.rs"), "pub fn v2() {}\n").unwrap();
+    fs::write(temp_dir.path().join("src").join("new.rs"), "pub fn new() {}\n").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.rs")
+        .exclude_patterns("main.rs")
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    // 見積もりもコミットの内容を数える
+    let estimate = processor.estimate_ref("HEAD", std::path::Path::new("src")).unwrap();
+    assert_eq!((estimate.files, estimate.total_size), (1, "pub fn v1() {}\n".len() as u64));
+    processor.process_ref("HEAD", std::path::Path::new("src")).unwrap();
+
+    assert_eq!(processor.get_result(), "```src/lib.rs\npub fn v1() {}\n```\n");
+    assert!(processor.process_ref("no-such-ref", std::path::Path::new(".")).is_err());
+}
+
+#[test]
+fn test_builder_repo_relative() {
+    let temp_dir = TempDir::new().unwrap();
+    let sub_dir = temp_dir.path().join("crates").join("core");
+    fs::create_dir_all(sub_dir.join("src")).unwrap();
+    fs::create_dir(temp_dir.path().join(".git")).unwrap();
+    fs::write(sub_dir.join("src").join("lib.rs"), "pub fn core() {}").unwrap();
+
+    // サブディレクトリから実行してもリポジトリのルートからのパスになる
+    let mut processor = CflBuilder::new()
+        .repo_relative(true)
+        .current_dir(&sub_dir)
+        .build()
+        .unwrap();
+    processor.process_path(&sub_dir).unwrap();
+    assert_eq!(processor.get_target_files()[0].path, "crates/core/src/lib.rs");
+
+    // リポジトリ外ではカレントディレクトリからのパスのまま
+    fs::remove_dir(temp_dir.path().join(".git")).unwrap();
+    let mut processor = CflBuilder::new()
+        .repo_relative(true)
+        .current_dir(&sub_dir)
+        .build()
+        .unwrap();
+    processor.process_path(&sub_dir).unwrap();
+    assert_eq!(processor.get_target_files()[0].path, "src/lib.rs");
+}
+
+#[test]
+fn test_builder_sort_within_groups() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join("src")).unwrap();
+    fs::create_dir(temp_dir.path().join("tests")).unwrap();
+    fs::write(temp_dir.path().join("src").join("a.rs"), "x".repeat(10)).unwrap();
+    fs::write(temp_dir.path().join("src").join("b.rs"), "x".repeat(30)).unwrap();
+    fs::write(temp_dir.path().join("src").join("c.rs"), "x".repeat(20)).unwrap();
+    fs::write(temp_dir.path().join("tests").join("big.rs"), "x".repeat(100)).unwrap();
+    fs::write(temp_dir.path().join("tests").join("small.rs"), "x".repeat(5)).unwrap();
+
+    let mut processor = CflBuilder::new()
+        .group_by_dir(true)
+        .sort(SortKey::Size)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // グループはディレクトリ順のまま、グループ内はサイズの大きい順
+    let result = processor.get_result();
+    let order: Vec<usize> = ["## src/", "src/b.rs", "src/c.rs", "src/a.rs", "## tests/", "tests/big.rs", "tests/small.rs"]
+        .iter()
+        .map(|needle| result.find(needle).unwrap())
+        .collect();
+    assert!(order.windows(2).all(|pair| pair[0] < pair[1]), "{}", result);
+}
+
+#[test]
+fn test_builder_count_extensions() {
+    let temp_dir = TempDir::new().unwrap();
+    let json = "{\"key\": [1, 2, 3], \"other\": {\"nested\": true}}";
+    let code = "fn main() { let values = vec![1, 2, 3]; }";
+    fs::write(temp_dir.path().join("data.json"), json).unwrap();
+    fs::write(temp_dir.path().join("main.rs"), code).unwrap();
+
+    let mut processor = CflBuilder::new()
+        .count_extensions(["rs"])
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let tokens = |path: &str| {
+        processor.get_target_files().iter().find(|f| f.path == path).unwrap().tokens
+    };
+    // .rs は通常の推定、.json はサイズからの簡易推定
+    assert_eq!(tokens("main.rs"), crate::processor::estimate_tokens(code));
+    assert_eq!(tokens("data.json"), json.len().div_ceil(4));
+    assert_ne!(tokens("data.json"), crate::processor::estimate_tokens(json));
+}
+
+#[cfg(feature = "notebook")]
+#[test]
+fn test_builder_notebook_cells() {
+    let temp_dir = TempDir::new().unwrap();
+    let notebook = r##"{
+ "cells": [
+  {"cell_type": "markdown", "metadata": {}, "source": ["# Title\n", "Some notes"]},
+  {"cell_type": "code", "execution_count": 1, "metadata": {}, "outputs": [{"output_type": "stream", "name": "stdout", "text": ["OUTPUT_TEXT\n"]}], "source": ["import os\n", "print(os.getcwd())"]},
+  {"cell_type": "code", "execution_count": 2, "metadata": {}, "outputs": [], "source": "x = 1\n"}
+ ],
+ "metadata": {"kernelspec": {"name": "python3"}},
+ "nbformat": 4,
+ "nbformat_minor": 5
+}"##;
+    fs::write(temp_dir.path().join("analysis.ipynb"), notebook).unwrap();
+
+    let mut processor = CflBuilder::new()
+        .notebook_cells(crate::NotebookCells::Code)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // セルのソースだけが残り、JSONの構造や出力は含まれない
+    let result = processor.get_result();
+    assert!(result.contains("# %%\nimport os\nprint(os.getcwd())\n\n# %%\nx = 1\n"), "{}", result);
+    for noise in ["cell_type", "OUTPUT_TEXT", "kernelspec", "nbformat", "Some notes", "{"] {
+        assert!(!result.contains(noise), "{}: {}", noise, result);
+    }
+
+    let mut processor = CflBuilder::new()
+        .notebook_cells(crate::NotebookCells::All)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    assert!(processor.get_result().contains("# %% [markdown]\n# # Title\n# Some notes\n\n# %%\nimport os"));
+}
+
+#[test]
+fn test_builder_readme_first() {
+    let temp_dir = TempDir::new().unwrap();
+    let docs = temp_dir.path().join("docs");
+    fs::create_dir(&docs).unwrap();
+    fs::write(docs.join("README.md"), "# Docs").unwrap();
+    fs::write(docs.join("guide.md"), "x".repeat(100)).unwrap();
+    fs::write(docs.join("notes.txt"), "x".repeat(50)).unwrap();
+
+    // サイズ順では最後になる README がディレクトリの先頭に来る
+    let mut processor = CflBuilder::new()
+        .sort(SortKey::Size)
+        .readme_first(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let result = processor.get_result();
+    let order: Vec<usize> = ["docs/README.md", "docs/guide.md", "docs/notes.txt"]
+        .iter()
+        .map(|needle| result.find(needle).unwrap())
+        .collect();
+    assert!(order.windows(2).all(|pair| pair[0] < pair[1]), "{}", result);
+}
+
+#[test]
+fn test_builder_use_dockerignore() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join(".git")).unwrap();
+    fs::create_dir(temp_dir.path().join("docs")).unwrap();
+    fs::write(temp_dir.path().join(".gitignore"), "*.log\n").unwrap();
+    fs::write(temp_dir.path().join(".dockerignore"), "docs/\n").unwrap();
+    fs::write(temp_dir.path().join("app.py"), "print('app')").unwrap();
+    fs::write(temp_dir.path().join("debug.log"), "log").unwrap();
+    fs::write(temp_dir.path().join("docs").join("guide.md"), "# Guide").unwrap();
+
+    let paths = |use_dockerignore: bool| {
+        let mut processor = CflBuilder::new()
+            .include_patterns("*.py,*.log,*.md")
+            .use_dockerignore(use_dockerignore)
+            .current_dir(temp_dir.path())
+            .build()
+            .unwrap();
+        processor.process_path(temp_dir.path()).unwrap();
+        let mut paths: Vec<String> = processor.get_target_files().iter().map(|f| f.path.clone()).collect();
+        paths.sort();
+        paths
+    };
+
+    // .gitignore のルールと組み合わせて適用される
+    assert_eq!(paths(false), vec!["app.py", "docs/guide.md"]);
+    assert_eq!(paths(true), vec!["app.py"]);
+}
+
+#[test]
+fn test_builder_process_globs() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join(".git")).unwrap();
+    fs::create_dir_all(temp_dir.path().join("src").join("sub")).unwrap();
+    fs::create_dir(temp_dir.path().join("nested")).unwrap();
+    fs::write(temp_dir.path().join(".gitignore"), "generated.rs\n").unwrap();
+    fs::write(temp_dir.path().join("Cargo.toml"), "[package]").unwrap();
+    fs::write(temp_dir.path().join("nested").join("Cargo.toml"), "[package]").unwrap();
+    fs::write(temp_dir.path().join("build.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("src").join("lib.rs"), "pub mod sub;").unwrap();
+    fs::write(temp_dir.path().join("src").join("notes.txt"), "notes").unwrap();
+    fs::write(temp_dir.path().join("src").join("generated.rs"), "// generated").unwrap();
+    fs::write(temp_dir.path().join("src").join("sub").join("mod.rs"), "pub fn f() {}").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    // 事前の見積もりも同じファイルを数える
+    let estimate = processor.estimate_globs(&["src/**/*.rs", "Cargo.toml"]).unwrap();
+    assert_eq!(estimate.files, 3);
+    assert_eq!(estimate.total_size, ("[package]".len() + "pub mod sub;".len() + "pub fn f() {}".len()) as u64);
+    processor.process_globs(&["src/**/*.rs", "Cargo.toml"]).unwrap();
+
+    // 2つのグロブの和集合だけが処理され、.gitignore も尊重される
+    let mut paths: Vec<&str> = processor.get_target_files().iter().map(|f| f.path.as_str()).collect();
+    paths.sort();
+    assert_eq!(paths, vec!["Cargo.toml", "src/lib.rs", "src/sub/mod.rs"]);
+}
+
+#[test]
+fn test_builder_embed_binary_under() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("icon.png"), b"\x89PNG\r\n\x1a\n\0\0").unwrap();
+    fs::write(temp_dir.path().join("large.bin"), vec![0u8; 1024]).unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .embed_binary_under(64)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // 小さいバイナリはMIMEタイプ付きのbase64ブロックになり、大きいものはスキップされる
+    let result = processor.get_result();
+    assert!(result.contains("```icon.png (image/png, base64)\niVBORw0KGgoAAA==\n"), "{}", result);
+    assert!(result.contains("fn main() {}"));
+    assert!(!result.contains("large.bin"));
+    let skipped = processor.get_skipped_files();
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].path, "large.bin");
+    assert_eq!(skipped[0].reason, crate::SkipReason::Binary);
+}
+
+#[test]
+fn test_builder_only_files() {
+    let temp_dir = setup_test_directory();
+    fs::write(temp_dir.path().join("src").join("staged.rs"), "// staged").unwrap();
+
+    // ステージされたファイルの集合を明示的に渡す（削除済みのファイルは無視される）
+    let mut processor = CflBuilder::new()
+        .only_files(["src/staged.rs", "Cargo.toml", "src/deleted.rs"])
+        .exclude_patterns("*.toml")
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let paths: Vec<&str> = processor.get_target_files().iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(paths, vec!["src/staged.rs"]);
+}
+
+#[test]
+fn test_builder_skip_paths() {
+    let temp_dir = setup_test_directory();
+    fs::write(temp_dir.path().join("out.md"), "```src/main.rs\n```\n").unwrap();
+    fs::create_dir(temp_dir.path().join("blocks")).unwrap();
+    fs::write(temp_dir.path().join("blocks").join("lib.rs.md"), "```src/lib.rs\n```\n").unwrap();
+
+    // 前回の出力ファイルと出力ディレクトリは処理しない（まだ存在しないパスも指定できる）
+    let mut processor = CflBuilder::new()
+        .skip_paths(["out.md", "blocks", "stats.json"])
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    processor.process_path(&temp_dir.path().join("out.md")).unwrap();
+
+    let mut paths: Vec<&str> = processor.get_target_files().iter().map(|f| f.path.as_str()).collect();
+    paths.sort();
+    assert_eq!(paths, vec!["Cargo.toml", "src/lib.rs", "src/main.rs", "tests/test.rs"]);
+}
+
+#[cfg(feature = "git")]
+#[test]
+fn test_staged_files() {
+    let temp_dir = setup_test_directory();
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .arg("-C")
+            .arg(temp_dir.path())
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    assert!(git(&["init", "-q"]).status.success());
+    assert!(git(&["add", "Cargo.toml"]).status.success());
+
+    assert_eq!(crate::git::staged_files(temp_dir.path()), vec![std::path::PathBuf::from("Cargo.toml")]);
+}
+
+#[test]
+fn test_builder_apply_default_excludes() {
+    let temp_dir = TempDir::new().unwrap();
+    let cache = temp_dir.path().join("pkg").join("__pycache__");
+    fs::create_dir_all(&cache).unwrap();
+    fs::create_dir(temp_dir.path().join("dist")).unwrap();
+    fs::write(temp_dir.path().join("pkg").join("mod.py"), "x = 1").unwrap();
+    fs::write(cache.join("mod.cpython-312.pyc.txt"), "cached").unwrap();
+    fs::write(temp_dir.path().join("dist").join("app.js"), "bundle").unwrap();
+
+    let paths = |builder: CflBuilder| {
+        let mut processor = builder.current_dir(temp_dir.path()).build().unwrap();
+        processor.process_path(temp_dir.path()).unwrap();
+        let mut paths: Vec<String> = processor.get_target_files().iter().map(|f| f.path.clone()).collect();
+        paths.sort();
+        paths
+    };
+
+    assert_eq!(paths(CflBuilder::new()).len(), 3);
+    assert_eq!(paths(CflBuilder::new().apply_default_excludes(true)), vec!["pkg/mod.py"]);
+    // ディレクトリ内を指す include で上書きできる
+    assert_eq!(
+        paths(CflBuilder::new().apply_default_excludes(true).include_patterns("*.py,dist/app.js")),
+        vec!["dist/app.js", "pkg/mod.py"]
+    );
+}
+
+#[test]
+fn test_builder_max_tokens_per_lang() {
+    let temp_dir = TempDir::new().unwrap();
+    for name in ["a.ts", "b.ts", "c.ts"] {
+        fs::write(temp_dir.path().join(name), "const value = 1;\n".repeat(20)).unwrap();
+    }
+    fs::write(temp_dir.path().join("main.rs"), "fn main() { let x = 1; }\n".repeat(50)).unwrap();
+    let ts_tokens = crate::processor::estimate_tokens(&"const value = 1;\n".repeat(20));
+
+    let mut limits = std::collections::HashMap::new();
+    limits.insert("typescript".to_string(), ts_tokens * 2 + 1);
+    let mut processor = CflBuilder::new()
+        .max_tokens_per_lang(limits)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // TypeScript は上限内の2ファイルだけ、Rust は制限されない
+    let files = processor.get_target_files();
+    let ts: Vec<_> = files.iter().filter(|f| f.path.ends_with(".ts")).collect();
+    assert_eq!(ts.len(), 2);
+    assert!(ts.iter().map(|f| f.tokens).sum::<usize>() <= ts_tokens * 2 + 1);
+    assert!(files.iter().any(|f| f.path == "main.rs"));
+
+    let skipped = processor.get_skipped_files();
+    assert_eq!(skipped.len(), 1);
+    assert!(skipped[0].path.ends_with(".ts"));
+    assert_eq!(skipped[0].reason, crate::SkipReason::LanguageTokenLimit);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_builder_resolve_symlinks_in_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join("real")).unwrap();
+    fs::create_dir(temp_dir.path().join("links")).unwrap();
+    fs::write(temp_dir.path().join("real").join("config.toml"), "key = 1").unwrap();
+    std::os::unix::fs::symlink("../real/config.toml", temp_dir.path().join("links").join("config.toml")).unwrap();
+
+    let header = |resolve: bool| {
+        let mut processor = CflBuilder::new()
+            .resolve_symlinks_in_paths(resolve)
+            .current_dir(temp_dir.path())
+            .build()
+            .unwrap();
+        processor.process_path(&temp_dir.path().join("links").join("config.toml")).unwrap();
+        processor.get_result().lines().next().unwrap().to_string()
+    };
+
+    // 既定ではシンボリックリンクのパス、有効にするとリンク先の実パスを表示する
+    assert_eq!(header(false), "```links/config.toml");
+    assert_eq!(header(true), "```real/config.toml");
+}
+
+#[test]
+fn test_builder_from_options() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join(".git")).unwrap();
+    fs::write(temp_dir.path().join(".gitignore"), "generated.rs\n").unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("generated.rs"), "fn generated() {}").unwrap();
+    fs::write(temp_dir.path().join("notes.md"), "# Notes").unwrap();
+
+    let process = |options: crate::CflOptions| {
+        let mut processor = CflBuilder::from_options(options)
+            .current_dir(temp_dir.path())
+            .build()
+            .unwrap();
+        processor.process_path(temp_dir.path()).unwrap();
+        processor
+    };
+
+    // 既定では .gitignore が適用される
+    let processor = process(crate::CflOptions {
+        include: Some("*.rs".to_string()),
+        ..crate::CflOptions::default()
+    });
+    let paths: Vec<_> = processor.get_target_files().iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(paths, vec!["main.rs"]);
+    assert!(processor.get_result().contains("```main.rs"));
+
+    // include に一致し、gitignore を無視するので generated.rs も含まれる
+    let processor = process(crate::CflOptions {
+        include: Some("*.rs".to_string()),
+        respect_gitignore: false,
+        format: OutputFormat::Raw,
+        sort: Some(SortKey::Path),
+        ..crate::CflOptions::default()
+    });
+    let paths: Vec<_> = processor.get_target_files().iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(paths.len(), 2);
+    assert!(paths.contains(&"generated.rs"));
+    assert!(paths.contains(&"main.rs"));
+
+    // Raw 形式で出力される
+    assert!(!processor.get_result().contains("```"));
+}
+
+#[test]
+fn test_builder_token_model() {
+    let temp_dir = TempDir::new().unwrap();
+    let code = "fn main() {\n    println!(\"Hello, world!\");\n}\n";
+    fs::write(temp_dir.path().join("main.rs"), code).unwrap();
+
+    let tokens = |model: crate::TokenModel| {
+        let mut processor = CflBuilder::new()
+            .token_model(model)
+            .current_dir(temp_dir.path())
+            .build()
+            .unwrap();
+        processor.process_path(temp_dir.path()).unwrap();
+        processor.get_total_tokens()
+    };
+
+    // 単語5つと記号12個: 汎用は単語のみ、各モデルは文字数/トークンと記号の重みで数える
+    assert_eq!(tokens(crate::TokenModel::Generic), 5);
+    assert_eq!(tokens(crate::TokenModel::Gpt4), 20);
+    assert_eq!(tokens(crate::TokenModel::Claude), 21);
+    assert_eq!(tokens(crate::TokenModel::Gemini), 17);
+}
+
+#[cfg(feature = "sniff")]
+#[test]
+fn test_builder_text_only() {
+    let temp_dir = TempDir::new().unwrap();
+    // PNGのシグネチャで始まるが、NULを含まず latin1 では文字列として読める
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png.extend_from_slice(b"IHDR image data");
+    fs::write(temp_dir.path().join("image.png"), &png).unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+
+    let process = |text_only: bool| {
+        let mut processor = CflBuilder::new()
+            .fallback_encoding(Some("latin1".to_string()))
+            .text_only(text_only)
+            .current_dir(temp_dir.path())
+            .build()
+            .unwrap();
+        processor.process_path(temp_dir.path()).unwrap();
+        processor
+    };
+
+    // 既定ではフォールバックのエンコーディングでテキストとして読まれる
+    let processor = process(false);
+    assert!(processor.get_target_files().iter().any(|f| f.path == "image.png"));
+
+    // 内容からPNGと判定され、バイナリとして除外される
+    let processor = process(true);
+    let files = processor.get_target_files();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].path, "main.rs");
+    let skipped = processor.get_skipped_files();
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].path, "image.png");
+    assert_eq!(skipped[0].reason, crate::SkipReason::Binary);
+}
+
+#[test]
+fn test_builder_fail_on_binary() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("logo.png"), [0x89, b'P', b'N', b'G', 0, 0, 0, 13]).unwrap();
+
+    let process = |fail_on_binary: bool| {
+        let mut processor = CflBuilder::new()
+            .fail_on_binary(fail_on_binary)
+            .current_dir(temp_dir.path())
+            .build()
+            .unwrap();
+        processor.process_path(temp_dir.path()).map(|_| processor)
+    };
+
+    // 既定ではバイナリファイルをスキップする
+    let processor = process(false).unwrap();
+    assert_eq!(processor.get_target_files().len(), 1);
+    assert_eq!(processor.get_skipped_files()[0].path, "logo.png");
+
+    // 有効にするとファイル名を含むエラーになる
+    let err = process(true).err().unwrap();
+    assert!(matches!(
+        err.downcast_ref::<crate::CflError>(),
+        Some(crate::CflError::BinaryFile(path)) if path == "logo.png"
+    ));
+    assert!(err.to_string().contains("logo.png"));
+}
+
+#[test]
+fn test_builder_modified_within() {
+    use std::time::{Duration, SystemTime};
+
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("recent.rs"), "fn recent() {}").unwrap();
+    fs::write(temp_dir.path().join("old.rs"), "fn old() {}").unwrap();
+    let old = fs::File::options().write(true).open(temp_dir.path().join("old.rs")).unwrap();
+    old.set_modified(SystemTime::now() - Duration::from_secs(3 * 24 * 60 * 60)).unwrap();
+
+    let mut processor = CflBuilder::new()
+        .modified_within(crate::cli::parse_duration("24h").unwrap())
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // 3日前に更新されたファイルは24時間の範囲外
+    let files = processor.get_target_files();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].path, "recent.rs");
+
+    assert_eq!(crate::cli::parse_duration("7d"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+    assert!(crate::cli::parse_duration("soon").is_err());
+    // 単位を掛けて溢れる値はパニックせずエラーになる
+    assert_eq!(
+        crate::cli::parse_duration("18446744073709551615d"),
+        Err("duration too long: 18446744073709551615d".to_string())
+    );
+}
+
+#[test]
+fn test_builder_configure_walk() {
+    let temp_dir = setup_test_directory();
+    fs::write(temp_dir.path().join("README.md"), "# Project").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .configure_walk(|walk: &mut crate::WalkBuilder| {
+            walk.max_depth(Some(1));
+        })
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // 最上位のファイルだけが処理される
+    let files = processor.get_target_files();
+    assert!(!files.is_empty());
+    assert!(files.iter().all(|f| !f.path.contains('/')), "{:?}", files);
+    assert!(files.iter().any(|f| f.path == "README.md"));
+}
+
+#[test]
+fn test_summarize_matches_full_run() {
+    let temp_dir = setup_test_directory();
+    let options = crate::CflOptions {
+        exclude: Some("tests".to_string()),
+        ..crate::CflOptions::default()
+    };
+
+    let mut processor = CflBuilder::from_options(options.clone())
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    assert!(!processor.get_result().is_empty());
+
+    let stats = crate::summarize(temp_dir.path(), options).unwrap();
+    assert_eq!(stats, processor.stats());
+    assert!(stats.files > 0);
+
+    // collect_content(false) では結果を組み立てない
+    let mut processor = CflBuilder::new()
+        .collect_content(false)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    assert_eq!(processor.get_result(), "");
+    assert!(processor.get_total_tokens() > 0);
+}
+
+#[test]
+fn test_interrupt_flag_keeps_partial_result() {
+    use crate::sink::{deliver, Sink};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let temp_dir = setup_test_directory();
+    let flag = Arc::new(AtomicBool::new(false));
+    let mut processor = CflBuilder::new()
+        .include_patterns("*.rs")
+        .interrupt_flag(flag.clone())
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(&temp_dir.path().join("src")).unwrap();
+    let collected = processor.get_target_files().len();
+    assert_eq!(collected, 2);
+    assert!(!processor.is_interrupted());
+
+    // 中断後の処理ではファイルを追加しない
+    flag.store(true, Ordering::Relaxed);
+    processor.process_path(&temp_dir.path().join("tests")).unwrap();
+    assert!(processor.is_interrupted());
+    assert_eq!(processor.stats().files, collected);
+
+    // 書き出される結果には中断前に集めたファイルのブロックがすべて含まれる
+    let mut stdout = Vec::new();
+    deliver(
+        processor.get_result(),
+        None,
+        &[Sink::Stdout],
+        &mut stdout,
+        &mut crate::clipboard::SystemClipboard::default(),
+    )
+    .unwrap();
+    let output = String::from_utf8(stdout).unwrap();
+    assert_eq!(output.matches("```src/").count(), collected);
+    assert!(output.contains("```src/main.rs\n"));
+    assert!(output.contains("```src/lib.rs\n"));
+    assert!(!output.contains("tests/test.rs"));
+}
+
+#[test]
+fn test_default_order_is_deterministic() {
+    let temp_dir = TempDir::new().unwrap();
+    // 作成順とパス順が異なるようにする
+    for name in ["zeta", "alpha", "mid", "beta"] {
+        fs::create_dir_all(temp_dir.path().join(name)).unwrap();
+        fs::write(temp_dir.path().join(name).join("b.rs"), format!("// {}/b", name)).unwrap();
+        fs::write(temp_dir.path().join(name).join("a.rs"), format!("// {}/a", name)).unwrap();
+    }
+    fs::write(temp_dir.path().join("top.rs"), "// top").unwrap();
+
+    let run = || {
+        let mut processor = CflBuilder::new()
+            .current_dir(temp_dir.path())
+            .build()
+            .unwrap();
+        processor.process_path(temp_dir.path()).unwrap();
+        processor
+    };
+
+    let first = run();
+    let second = run();
+    assert_eq!(first.get_result().as_bytes(), second.get_result().as_bytes());
+
+    // 読み込みと出力はパス順
+    let paths: Vec<_> = first.get_target_files().iter().map(|f| f.path.as_str()).collect();
+    let mut sorted = paths.clone();
+    sorted.sort();
+    assert_eq!(paths, sorted);
+    assert_eq!(paths.first(), Some(&"alpha/a.rs"));
+    assert_eq!(paths.last(), Some(&"zeta/b.rs"));
+}
@@ -0,0 +1,385 @@
+use clap::Parser;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+use crate::cli::{count_tokens_output, languages_output, Cli};
+use crate::color::Colors;
+use crate::FileProcessor;
+
+fn setup_test_directory() -> TempDir {
+    let temp_dir = TempDir::new().unwrap();
+
+    fs::create_dir(temp_dir.path().join("src")).unwrap();
+    fs::write(
+        temp_dir.path().join("src/main.rs"),
+        "fn main() { println!(\"Hello\"); }"
+    ).unwrap();
+    fs::write(
+        temp_dir.path().join("src/lib.rs"),
+        "pub fn add(a: i32, b: i32) -> i32 { a + b }"
+    ).unwrap();
+    fs::write(
+        temp_dir.path().join("data.json"),
+        format!("[{}]", "1,".repeat(2_000))
+    ).unwrap();
+
+    temp_dir
+}
+
+/// CLI引数からプロセッサを構築して処理する
+fn run_cli(args: &[&str], current_dir: &Path) -> FileProcessor {
+    let cli = Cli::parse_from(args);
+    let mut processor = cli.builder(current_dir).build().unwrap();
+    for path in cli.paths() {
+        processor.process_path(&current_dir.join(path)).unwrap();
+    }
+    processor
+}
+
+#[test]
+fn test_count_tokens_only_output() {
+    let temp_dir = setup_test_directory();
+    let processor = run_cli(
+        &["cfl", ".", "--count-tokens-only", "-i", "*.rs,*.json", "--max-size", "1k"],
+        temp_dir.path(),
+    );
+
+    let output = count_tokens_output(&processor);
+    assert!(output.chars().all(|c| c.is_ascii_digit()));
+    assert_eq!(output, processor.get_total_tokens().to_string());
+    // max-sizeによりdata.jsonは除外される
+    assert_eq!(processor.get_target_files().len(), 2);
+    assert_eq!(output, "14");
+}
+
+#[test]
+fn test_parse_separator_escapes() {
+    let cli = Cli::parse_from(["cfl", ".", "--separator", "\\n---\\n\\t\\\\"]);
+    assert_eq!(cli.separator.as_deref(), Some("\n---\n\t\\"));
+
+    assert!(Cli::try_parse_from(["cfl", ".", "--separator", "\\x"]).is_err());
+}
+
+#[test]
+fn test_no_clipboard_requires_output() {
+    let no_env = |_: &str| None;
+
+    let cli = Cli::parse_from(["cfl", ".", "--no-clipboard"]);
+    let err = cli.check_clipboard(no_env).unwrap_err();
+    assert!(err.to_string().contains("use --stdout or --output"));
+
+    let cli = Cli::parse_from(["cfl", ".", "--no-clipboard", "--stdout"]);
+    assert!(cli.check_clipboard(no_env).is_ok());
+    let cli = Cli::parse_from(["cfl", ".", "--no-clipboard", "-o", "out.md"]);
+    assert!(cli.check_clipboard(no_env).is_ok());
+
+    // 環境変数でも無効化できる
+    let cli = Cli::parse_from(["cfl", "."]);
+    assert!(cli.check_clipboard(no_env).is_ok());
+    assert!(cli
+        .check_clipboard(|name| (name == "CFL_NO_CLIPBOARD").then(|| "1".to_string()))
+        .is_err());
+    assert!(cli
+        .check_clipboard(|name| (name == "CFL_NO_CLIPBOARD").then(|| "0".to_string()))
+        .is_ok());
+}
+
+#[test]
+fn test_color_disabled() {
+    let no_env = |_: &str| None;
+    let no_color_env = |name: &str| (name == "NO_COLOR").then(|| "1".to_string());
+
+    let cli = Cli::parse_from(["cfl", "."]);
+    assert!(cli.color_enabled(true, no_env));
+    // 端末でない場合や NO_COLOR が設定されている場合は色を付けない
+    assert!(!cli.color_enabled(false, no_env));
+    assert!(!cli.color_enabled(true, no_color_env));
+    let cli = Cli::parse_from(["cfl", ".", "--no-color"]);
+    assert!(!cli.color_enabled(true, no_env));
+
+    let colors = Colors::new(cli.color_enabled(true, no_env));
+    assert_eq!(colors.number("1,234"), "1,234");
+    assert!(!colors.warning("warning").contains('\x1b'));
+    assert!(Colors::new(true).number("1,234").contains("\x1b["));
+}
+
+#[test]
+fn test_parse_rename() {
+    let cli = Cli::parse_from(["cfl", ".", "--rename", "a/b.rs=x.rs", "--rename", "lib=vendor"]);
+    assert_eq!(
+        cli.rename,
+        vec![
+            ("a/b.rs".to_string(), "x.rs".to_string()),
+            ("lib".to_string(), "vendor".to_string()),
+        ]
+    );
+
+    assert!(Cli::try_parse_from(["cfl", ".", "--rename", "missing-target"]).is_err());
+}
+
+#[test]
+fn test_root_guard() {
+    let temp_dir = setup_test_directory();
+    let home = temp_dir.path();
+
+    let cli = Cli::parse_from(["cfl", "."]);
+    let err = cli.check_root_guard(home, Some(home)).unwrap_err();
+    assert!(err.to_string().contains("pass --force to proceed"));
+    assert!(cli.check_root_guard(Path::new("/"), None).is_err());
+    assert!(cli.check_root_guard(&home.join("src"), Some(home)).is_ok());
+
+    let cli = Cli::parse_from(["cfl", "src,..", "--force"]);
+    assert!(cli.check_root_guard(&home.join("src"), Some(home)).is_ok());
+    let cli = Cli::parse_from(["cfl", "src,.."]);
+    assert!(cli.check_root_guard(&home.join("src"), Some(home)).is_err());
+
+    // --from-glob の走査元も対象にする
+    let cli = Cli::parse_from(["cfl", "--from-glob", "**/*.rs"]);
+    assert!(cli.check_root_guard(home, Some(home)).is_err());
+    assert!(cli.check_root_guard(&home.join("src"), Some(home)).is_ok());
+    let cli = Cli::parse_from(["cfl", "--from-glob", "**/*.rs", "--force"]);
+    assert!(cli.check_root_guard(home, Some(home)).is_ok());
+}
+
+#[test]
+fn test_embed_command() {
+    let temp_dir = setup_test_directory();
+    let processor = run_cli(
+        &["cfl", ".", "-i", "*.rs", "-e", "lib.rs,data.json", "--embed-command", "--hash", "--stdout"],
+        temp_dir.path(),
+    );
+
+    assert!(processor.get_result().starts_with(
+        "```sh\ncfl . -i '*.rs' -e lib.rs,data.json --hash\n```\n\n```src/main.rs sha256:"
+    ));
+
+    // 絶対パスはカレントディレクトリやホームからの相対パスで表示する
+    let cli = Cli::parse_from(["cfl", "/work/app/src,/home/me/notes.md,/etc/hosts", "-i", "it's"]);
+    assert_eq!(
+        cli.command_line(Path::new("/work/app"), Some(Path::new("/home/me"))),
+        "cfl src,~/notes.md,/etc/hosts -i 'it'\\''s'"
+    );
+}
+
+#[test]
+fn test_command_line_records_output_flags() {
+    let cli = Cli::parse_from([
+        "cfl",
+        ".",
+        "--max-size-for",
+        "json=1k,lock=0",
+        "--max-line-length",
+        "200",
+        "--long-lines",
+        "skip",
+        "--separator",
+        "\\n---\\n\\t\\\\",
+        "--with-git-info",
+    ]);
+    let command = cli.command_line(Path::new("/work"), None);
+    assert!(command.contains(" --max-size-for json=1024,lock=0"), "{}", command);
+    assert!(command.contains(" --max-line-length 200"), "{}", command);
+    assert!(command.contains(" --long-lines skip"), "{}", command);
+    assert!(command.contains(" --separator '\\n---\\n\\t\\\\'"), "{}", command);
+    assert!(command.contains(" --with-git-info"), "{}", command);
+
+    // 記録したコマンドを再び解析すると同じ設定になる
+    let args: Vec<String> = command.split(' ').map(|arg| arg.trim_matches('\'').to_string()).collect();
+    let reparsed = Cli::parse_from(&args);
+    assert_eq!(reparsed.separator, cli.separator);
+    assert_eq!(reparsed.max_size_for, cli.max_size_for);
+    assert_eq!(reparsed.long_lines, cli.long_lines);
+}
+
+#[cfg(feature = "git")]
+#[test]
+fn test_command_line_records_ref() {
+    let cli = Cli::parse_from(["cfl", "src", "--ref", "HEAD~3"]);
+    assert_eq!(cli.command_line(Path::new("/work"), None), "cfl src --ref HEAD~3");
+}
+
+#[test]
+fn test_null_separated_stdin_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("my file.rs"), "fn spaced() {}").unwrap();
+    fs::write(temp_dir.path().join("plain.rs"), "fn plain() {}").unwrap();
+    fs::write(temp_dir.path().join("other.rs"), "fn other() {}").unwrap();
+
+    let cli = Cli::parse_from(["cfl", "-", "-0"]);
+    assert!(cli.reads_paths_from_stdin());
+    assert!(cli.null);
+
+    // find -print0 のように末尾にもNULが付く
+    let input = b"my file.rs\0plain.rs\0";
+    let paths = crate::cli::split_path_list(input, cli.null);
+    assert_eq!(paths, vec![Path::new("my file.rs"), Path::new("plain.rs")]);
+
+    let mut processor = cli.builder(temp_dir.path()).build().unwrap();
+    for path in &paths {
+        processor.process_path(&temp_dir.path().join(path)).unwrap();
+    }
+    let result = processor.get_result();
+    assert!(result.contains("fn spaced() {}"));
+    assert!(result.contains("fn plain() {}"));
+    assert!(!result.contains("fn other() {}"));
+
+    // 改行区切りではスペースを含むパスも1行1パスとして扱う
+    assert_eq!(
+        crate::cli::split_path_list(b"my file.rs\r\nplain.rs\n", false),
+        vec![Path::new("my file.rs"), Path::new("plain.rs")]
+    );
+}
+
+#[test]
+fn test_preflight_warning() {
+    let temp_dir = setup_test_directory();
+    let check = |args: &[&str]| {
+        let cli = Cli::parse_from(args);
+        let processor = cli.builder(temp_dir.path()).build().unwrap();
+        let estimate = processor.estimate(temp_dir.path()).unwrap();
+        assert_eq!(estimate.files, 3);
+        cli.check_preflight(&estimate)
+    };
+
+    // 閾値以下では何も出さない
+    assert_eq!(check(&["cfl", ".", "--max-total-files-warning", "3"]).unwrap(), None);
+    assert_eq!(check(&["cfl", "."]).unwrap(), None);
+
+    let warning = check(&["cfl", ".", "--max-total-files-warning", "2"]).unwrap().unwrap();
+    assert!(warning.contains("About 3 files"), "{}", warning);
+
+    // 厳密モードでは --force がなければ停止する
+    assert!(check(&["cfl", ".", "--max-total-files-warning", "2", "--preflight-strict"]).is_err());
+    assert!(check(&["cfl", ".", "--max-total-files-warning", "2", "--preflight-strict", "--force"]).unwrap().is_some());
+}
+
+#[test]
+fn test_preflight_follows_selection() {
+    use std::time::{Duration, SystemTime};
+
+    let temp_dir = setup_test_directory();
+    for name in ["src/lib.rs", "data.json"] {
+        let file = fs::File::options().write(true).open(temp_dir.path().join(name)).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(3 * 24 * 60 * 60)).unwrap();
+    }
+    let check = |args: &[&str]| {
+        let cli = Cli::parse_from(args);
+        let processor = cli.builder(temp_dir.path()).build().unwrap();
+        let estimate = processor.estimate(temp_dir.path()).unwrap();
+        cli.check_preflight(&estimate).map(|warning| (estimate.files, warning))
+    };
+
+    // 実際の処理と同じく、最近更新されたファイルだけを数える
+    let args = ["cfl", ".", "--modified-within", "24h", "--max-total-files-warning", "1", "--preflight-strict"];
+    assert_eq!(check(&args).unwrap(), (1, None));
+
+    // ステージされたファイルだけを数える
+    #[cfg(feature = "git")]
+    {
+        let git = |args: &[&str]| {
+            std::process::Command::new("git").arg("-C").arg(temp_dir.path()).args(args).output().unwrap()
+        };
+        assert!(git(&["init", "-q"]).status.success());
+        assert!(git(&["add", "src/lib.rs"]).status.success());
+        let args = ["cfl", ".", "--staged", "--max-total-files-warning", "1", "--preflight-strict"];
+        assert_eq!(check(&args).unwrap(), (1, None));
+    }
+}
+
+#[test]
+fn test_file_listing_limit() {
+    let files: Vec<crate::FileInfo> = (0..5)
+        .map(|i| crate::FileInfo {
+            path: format!("src/file{}.rs", i),
+            size: 1200,
+            tokens: 300,
+            hash: None,
+            mime: None,
+        })
+        .collect();
+
+    // 先頭の N 件と残りの件数だけを表示する
+    let listing = crate::cli::file_listing(&files, Some(2), ',');
+    assert_eq!(
+        listing,
+        "  • src/file0.rs (1,200 bytes, 300 tokens)\n  • src/file1.rs (1,200 bytes, 300 tokens)\n  ... and 3 more\n"
+    );
+
+    // 上限がなければすべて表示する
+    let listing = crate::cli::file_listing(&files, None, ',');
+    assert_eq!(listing.lines().count(), 5);
+    assert!(!listing.contains("more"));
+    assert_eq!(crate::cli::file_listing(&files, Some(5), ','), listing);
+}
+
+#[test]
+fn test_repeated_include_flags() {
+    let temp_dir = setup_test_directory();
+    fs::write(temp_dir.path().join("Cargo.toml"), "[package]").unwrap();
+    fs::write(temp_dir.path().join("notes.md"), "# Notes").unwrap();
+
+    // 複数の -i はすべて適用され、各値のカンマ区切りも引き続き使える
+    let processor = run_cli(&["cfl", ".", "-i", "*.rs", "-i", "*.toml,*.json"], temp_dir.path());
+    let mut paths: Vec<_> = processor.get_target_files().iter().map(|f| f.path.clone()).collect();
+    paths.sort();
+    assert_eq!(paths, vec!["Cargo.toml", "data.json", "src/lib.rs", "src/main.rs"]);
+
+    let processor = run_cli(&["cfl", ".", "-e", "*.json", "-e", "*.md"], temp_dir.path());
+    assert_eq!(processor.get_target_files().len(), 3);
+}
+
+#[test]
+fn test_template_file() {
+    let temp_dir = setup_test_directory();
+    let template = temp_dir.path().join("block.tmpl");
+    fs::write(&template, "### {path} ({lang})\n~~~\n{content}~~~\n").unwrap();
+    fs::write(temp_dir.path().join("braces.rs"), "fn f() -> &'static str { \"{path}\" }").unwrap();
+
+    let processor = run_cli(
+        &["cfl", "src/main.rs,braces.rs", "--template-file", template.to_str().unwrap()],
+        temp_dir.path(),
+    );
+
+    // テンプレートに従って出力され、内容中のプレースホルダーは置換されない
+    assert_eq!(
+        processor.get_result(),
+        "### src/main.rs (rust)\n~~~\nfn main() { println!(\"Hello\"); }\n~~~\n\n\
+         ### braces.rs (rust)\n~~~\nfn f() -> &'static str { \"{path}\" }\n~~~\n"
+    );
+
+    // 読み込み時にプレースホルダーを検証する
+    fs::write(&template, "### {file}\n{content}\n").unwrap();
+    let err = Cli::try_parse_from(["cfl", ".", "--template-file", template.to_str().unwrap()]).err().unwrap();
+    assert!(err.to_string().contains("unknown placeholder {file}"), "{}", err);
+    fs::write(&template, "### {path}\n").unwrap();
+    assert!(Cli::try_parse_from(["cfl", ".", "--template-file", template.to_str().unwrap()]).is_err());
+}
+
+#[test]
+fn test_list_languages() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join("src")).unwrap();
+    fs::write(temp_dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("src/lib.rs"), "pub fn f() {}").unwrap();
+    fs::write(temp_dir.path().join("tool.py"), "print('hi')").unwrap();
+    fs::write(temp_dir.path().join("NOTICE"), "notice").unwrap();
+
+    let processor = run_cli(&["cfl", ".", "--list-languages"], temp_dir.path());
+    // 内容は組み立てない
+    assert_eq!(processor.get_result(), "");
+    assert_eq!(
+        languages_output(&processor.stats(), ','),
+        "rust: 2\npython: 1\n(other): 1\n"
+    );
+}
+
+#[test]
+fn test_parse_size_overflow() {
+    use crate::cli::parse_size;
+
+    assert_eq!(parse_size("2k"), Ok(2048));
+    // 単位を掛けて溢れる値はパニックせずエラーになる
+    assert_eq!(parse_size("18446744073709551615g"), Err("size too large: 18446744073709551615g".to_string()));
+    assert!(Cli::try_parse_from(["cfl", ".", "--max-size", "18446744073709551615g"]).is_err());
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::fs;
+use tempfile::TempDir;
+
+use crate::clipboard::{detect_backend, Clipboard, ClipboardBackend};
+use crate::{CflBuilder, CflError};
+
+fn env_with(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    move |name| vars.get(name).cloned()
+}
+
+#[test]
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn test_detect_backend() {
+    assert_eq!(
+        detect_backend(env_with(&[("WAYLAND_DISPLAY", "wayland-0"), ("DISPLAY", ":0")])),
+        Some(ClipboardBackend::Wayland)
+    );
+    assert_eq!(
+        detect_backend(env_with(&[("DISPLAY", ":0")])),
+        Some(ClipboardBackend::X11)
+    );
+    // 空の値は未設定として扱う
+    assert_eq!(
+        detect_backend(env_with(&[("WAYLAND_DISPLAY", ""), ("DISPLAY", ":0")])),
+        Some(ClipboardBackend::X11)
+    );
+    assert_eq!(detect_backend(env_with(&[])), None);
+}
+
+/// 設定された内容を記録するテスト用クリップボード
+#[derive(Default)]
+struct FakeClipboard {
+    text: Option<String>,
+    html: Option<String>,
+}
+
+impl Clipboard for FakeClipboard {
+    fn set_text(&mut self, text: &str) -> Result<(), CflError> {
+        self.text = Some(text.to_string());
+        Ok(())
+    }
+
+    fn set_html(&mut self, html: &str, alt_text: &str) -> Result<(), CflError> {
+        self.html = Some(html.to_string());
+        self.text = Some(alt_text.to_string());
+        Ok(())
+    }
+}
+
+#[test]
+fn test_deliver_html() {
+    use crate::sink::{deliver, Sink};
+
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() { if 1 < 2 {} }\n").unwrap();
+    fs::write(temp_dir.path().join("notes.txt"), "a & b\n").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let mut clipboard = FakeClipboard::default();
+    let html = processor.get_html();
+    deliver(processor.get_result(), Some(&html), &[Sink::Clipboard], &mut Vec::new(), &mut clipboard).unwrap();
+
+    let html = clipboard.html.unwrap();
+    assert_eq!(html.matches("<pre>").count(), 2);
+    assert!(html.contains(
+        "<h3>main.rs</h3>\n<pre><code class=\"language-rust\">fn main() { if 1 &lt; 2 {} }\n</code></pre>\n"
+    ));
+    assert!(html.contains("<pre><code class=\"language-text\">a &amp; b\n</code></pre>"));
+    assert_eq!(clipboard.text.as_deref(), Some(processor.get_result()));
+}
+
+#[test]
+fn test_deliver_to_several_sinks() {
+    use crate::sink::{deliver, Sink};
+    use clap::Parser;
+
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+    let mut processor = CflBuilder::new()
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // --output と --clipboard を併用すると両方に書き込む
+    let output = temp_dir.path().join("ctx.md");
+    let cli = crate::cli::Cli::parse_from(["cfl", ".", "--output", output.to_str().unwrap(), "--clipboard"]);
+    let sinks = cli.sinks();
+    assert_eq!(sinks, vec![Sink::File(output.clone()), Sink::Clipboard]);
+
+    let mut stdout = Vec::new();
+    let mut clipboard = FakeClipboard::default();
+    deliver(processor.get_result(), None, &sinks, &mut stdout, &mut clipboard).unwrap();
+
+    assert_eq!(fs::read_to_string(&output).unwrap(), processor.get_result());
+    assert_eq!(clipboard.text.as_deref(), Some(processor.get_result()));
+    assert!(stdout.is_empty());
+
+    // --stdout と --output も併用でき、クリップボードは使わない
+    let cli = crate::cli::Cli::parse_from(["cfl", ".", "--stdout", "--output", output.to_str().unwrap()]);
+    assert_eq!(cli.sinks(), vec![Sink::File(output), Sink::Stdout]);
+    assert_eq!(crate::cli::Cli::parse_from(["cfl", "."]).sinks(), vec![Sink::Clipboard]);
+}
@@ -0,0 +1,526 @@
+use std::fs;
+use tempfile::TempDir;
+
+use crate::{format_content, render, CflBuilder, FileInfo, FormatOptions, OutputFormat, RenderOptions, SortKey};
+
+fn processed_result(file_name: &str, content: &str, collapse_imports: bool) -> String {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join(file_name), content).unwrap();
+
+    let mut processor = CflBuilder::new()
+        .collapse_imports(collapse_imports)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    processor.get_result().to_string()
+}
+
+#[test]
+fn test_format_content_matches_process_file() {
+    let content = "fn main() {\n    println!(\"Hello\");\n}\n";
+    let expected = processed_result("main.rs", content, false);
+
+    assert_eq!(
+        format_content("main.rs", content, &FormatOptions::default()),
+        expected
+    );
+}
+
+#[test]
+fn test_format_content_matches_process_file_with_options() {
+    let content = "use std::fs;\nuse std::path::Path;\n\nfn main() {}\n";
+    let expected = processed_result("main.rs", content, true);
+
+    let opts = FormatOptions {
+        collapse_imports: true,
+        ..Default::default()
+    };
+    assert_eq!(format_content("main.rs", content, &opts), expected);
+    assert!(expected.contains("// 2 imports collapsed"));
+}
+
+#[test]
+fn test_format_content_extends_fence_for_nested_blocks() {
+    let content = "# Example\n\n```rust\nfn main() {}\n```\n";
+    let expected = processed_result("README.md", content, false);
+    let block = format_content("README.md", content, &FormatOptions::default());
+
+    assert_eq!(block, expected);
+    assert!(block.starts_with("````README.md\n"));
+    assert!(block.ends_with("\n````\n"));
+}
+
+#[test]
+fn test_content_hash() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join("a")).unwrap();
+    fs::create_dir(temp_dir.path().join("b")).unwrap();
+    fs::write(temp_dir.path().join("a/same.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("b/same.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("other.rs"), "fn other() {}").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .hash(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let hash_of = |path: &str| {
+        processor
+            .get_target_files()
+            .iter()
+            .find(|f| f.path == path)
+            .and_then(|f| f.hash.clone())
+            .unwrap()
+    };
+    assert_eq!(hash_of("a/same.rs").len(), 8);
+    assert_eq!(hash_of("a/same.rs"), hash_of("b/same.rs"));
+    assert_ne!(hash_of("a/same.rs"), hash_of("other.rs"));
+    assert!(processor
+        .get_result()
+        .contains(&format!("```other.rs sha256:{}\n", hash_of("other.rs"))));
+}
+
+#[test]
+fn test_files_metadata_json() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("say \"hi\".txt"), "hello").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let json = crate::format::files_metadata_json(processor.get_target_files());
+    let entries: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(entries.len(), 2);
+    let main = entries.iter().find(|e| e["path"] == "main.rs").unwrap();
+    assert_eq!(main["size"], 12);
+    assert_eq!(main["language"], "rust");
+    assert!(main["tokens"].as_u64().unwrap() > 0);
+    assert!(main.get("content").is_none());
+    assert!(entries.iter().any(|e| e["path"] == "say \"hi\".txt"));
+}
+
+#[test]
+fn test_jsonl_output() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join("src")).unwrap();
+    fs::write(temp_dir.path().join("src/main.rs"), "fn main() {\n    println!(\"\\\"hi\\\"\");\n}\n").unwrap();
+    fs::write(temp_dir.path().join("README.md"), "# Title\n\n```sh\ncfl .\n```\n").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .output_format(crate::OutputFormat::Jsonl)
+        .group_by_dir(true)
+        .footer_stats(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let result = processor.get_result();
+    let lines: Vec<&str> = result.lines().collect();
+    assert_eq!(lines.len(), processor.get_target_files().len());
+    assert_eq!(lines.len(), 2);
+
+    for (line, file) in lines.iter().zip(processor.get_target_files()) {
+        let record: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(record["path"], file.path.as_str());
+        assert_eq!(record["size"], file.size);
+        assert_eq!(record["tokens"], file.tokens);
+        let content = fs::read_to_string(temp_dir.path().join(&file.path)).unwrap();
+        assert_eq!(record["content"], content.as_str());
+    }
+}
+
+#[test]
+fn test_json_escaping_round_trip() {
+    use crate::{SkipReason, SkippedFile, Stats};
+
+    // 制御文字・引用符・非ASCII文字を含むパスと内容
+    let path = "dir/\u{1}\u{1f}\u{7f} \"q\" \\ \t\r\n日本語 😀.rs";
+    let content = "a\u{0}b\u{8}c\u{c}\r\n\"\\ é\u{2028}\u{fffd}\n";
+    let file = FileInfo {
+        path: path.to_string(),
+        size: content.len(),
+        tokens: 3,
+        hash: Some("0a1b2c3d".to_string()),
+        mime: None,
+    };
+
+    let opts = FormatOptions {
+        format: OutputFormat::Jsonl,
+        ..FormatOptions::default()
+    };
+    let record: serde_json::Value =
+        serde_json::from_str(crate::format::format_block(&file, content, &opts).trim_end()).unwrap();
+    assert_eq!(record["path"], path);
+    assert_eq!(record["content"], content);
+
+    let entries: serde_json::Value =
+        serde_json::from_str(&crate::format::files_metadata_json(std::slice::from_ref(&file))).unwrap();
+    assert_eq!(entries[0]["path"], path);
+    assert_eq!(entries[0]["hash"], "0a1b2c3d");
+
+    let stats = Stats {
+        files: 1,
+        total_size: file.size,
+        total_tokens: file.tokens,
+        languages: [("言語 \"x\"\n".to_string(), 1)].into_iter().collect(),
+    };
+    let skipped = [SkippedFile { path: path.to_string(), reason: SkipReason::Unreadable }];
+    let json: serde_json::Value =
+        serde_json::from_str(&crate::format::stats_json(&stats, &[file], &skipped)).unwrap();
+    assert_eq!(json["files"][0]["path"], path);
+    assert_eq!(json["languages"]["言語 \"x\"\n"], 1);
+    assert_eq!(json["skipped"][0]["path"], path);
+    assert_eq!(json["errors"][0], path);
+}
+
+#[test]
+fn test_annotate() {
+    let opts = FormatOptions {
+        annotate: true,
+        ..FormatOptions::default()
+    };
+    let content = "fn main() {}\n".repeat(100);
+    let block = format_content("src/main.rs", &content, &opts);
+    assert!(block.starts_with("<!-- src/main.rs: 1,300 bytes, 200 tokens -->\n```src/main.rs\n"));
+
+    let raw = format_content(
+        "src/main.rs",
+        "fn main() {}",
+        &FormatOptions {
+            format: crate::OutputFormat::Raw,
+            ..opts.clone()
+        },
+    );
+    assert_eq!(raw, "// src/main.rs: 12 bytes, 2 tokens\n// ==== src/main.rs ====\nfn main() {}\n\n");
+
+    // デフォルトでは注釈を付けない
+    let plain = format_content("src/main.rs", "fn main() {}", &FormatOptions::default());
+    assert!(plain.starts_with("```src/main.rs\n"));
+}
+
+
+fn render_input() -> Vec<(FileInfo, String)> {
+    ["src/main.rs", "README.md"]
+        .iter()
+        .map(|path| {
+            let content = format!("// {}\n", path);
+            let file = FileInfo {
+                path: path.to_string(),
+                size: content.len(),
+                tokens: 2,
+                hash: None,
+                mime: None,
+            };
+            (file, content)
+        })
+        .collect()
+}
+
+#[test]
+fn test_render_formats() {
+    let files = render_input();
+    let render_as = |format: OutputFormat| {
+        let mut opts = RenderOptions::default();
+        opts.format.format = format;
+        render(&files, &opts)
+    };
+
+    assert_eq!(
+        render_as(OutputFormat::Markdown),
+        "```src/main.rs\n// src/main.rs\n```\n\n```README.md\n// README.md\n```\n"
+    );
+    assert_eq!(
+        render_as(OutputFormat::Raw),
+        "// ==== src/main.rs ====\n// src/main.rs\n\n\n// ==== README.md ====\n// README.md\n\n"
+    );
+    assert_eq!(
+        render_as(OutputFormat::Jsonl),
+        "{\"path\":\"src/main.rs\",\"size\":15,\"tokens\":2,\"content\":\"// src/main.rs\\n\"}\n\
+         {\"path\":\"README.md\",\"size\":13,\"tokens\":2,\"content\":\"// README.md\\n\"}\n"
+    );
+}
+
+#[test]
+fn test_trailing_newline_normalized() {
+    let render_with = |format: OutputFormat, content: &str| {
+        let file = FileInfo {
+            path: "src/lib.rs".to_string(),
+            size: 10,
+            tokens: 2,
+            hash: None,
+            mime: None,
+        };
+        let mut opts = RenderOptions::default();
+        opts.format.format = format;
+        render(&[(file, content.to_string())], &opts)
+    };
+
+    // 末尾の改行の有無や数に関係なく同じ出力になる
+    for format in [OutputFormat::Markdown, OutputFormat::Raw, OutputFormat::Jsonl] {
+        let expected = render_with(format, "fn a() {}\n");
+        for content in ["fn a() {}", "fn a() {}\n\n\n", "fn a() {}\n\r\n"] {
+            assert_eq!(render_with(format, content), expected, "{:?} {:?}", format, content);
+        }
+    }
+
+    // 閉じ区切りの直前に改行がちょうど1つ入る
+    assert_eq!(render_with(OutputFormat::Markdown, "fn a() {}\n\n"), "```src/lib.rs\nfn a() {}\n```\n");
+    assert_eq!(render_with(OutputFormat::Raw, "fn a() {}\n\n"), "// ==== src/lib.rs ====\nfn a() {}\n\n");
+    assert!(render_with(OutputFormat::Jsonl, "fn a() {}\n\n").contains("\"content\":\"fn a() {}\\n\"}"));
+
+    // CRLFの内容は最後の行もCRLFで終える
+    let expected = "```src/lib.rs\nfn a() {}\r\nfn b() {}\r\n```\n";
+    for content in ["fn a() {}\r\nfn b() {}", "fn a() {}\r\nfn b() {}\r\n", "fn a() {}\r\nfn b() {}\r\n\r\n"] {
+        assert_eq!(render_with(OutputFormat::Markdown, content), expected, "{:?}", content);
+    }
+
+    // 改行コードを保持したファイルの行末が混在しない
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("lib.rs"), "fn a() {}\r\nfn b() {}\r\n").unwrap();
+    for (keep, expected) in [
+        (true, "```lib.rs\nfn a() {}\r\nfn b() {}\r\n```\n"),
+        (false, "```lib.rs\nfn a() {}\nfn b() {}\n```\n"),
+    ] {
+        let mut processor = CflBuilder::new()
+            .keep_line_endings(keep)
+            .current_dir(temp_dir.path())
+            .build()
+            .unwrap();
+        processor.process_path(temp_dir.path()).unwrap();
+        assert_eq!(processor.get_result(), expected);
+    }
+}
+
+#[test]
+fn test_render_matches_get_result() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join("src")).unwrap();
+    fs::write(temp_dir.path().join("src").join("main.rs"), "fn main() {}\n").unwrap();
+    fs::write(temp_dir.path().join("Cargo.toml"), "[package]\n").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .group_by_dir(true)
+        .footer_stats(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // 収集済みのデータから同じ出力を再現できる
+    let files: Vec<(FileInfo, String)> = processor
+        .get_target_files()
+        .iter()
+        .map(|file| {
+            let content = fs::read_to_string(temp_dir.path().join(&file.path)).unwrap();
+            (file.clone(), content)
+        })
+        .collect();
+    let opts = RenderOptions {
+        group_by_dir: true,
+        footer_stats: true,
+        ..RenderOptions::default()
+    };
+    assert_eq!(render(&files, &opts), processor.get_result());
+}
+
+#[test]
+fn test_format_number_with_separator() {
+    use crate::format::{format_number, format_number_with};
+
+    for (num, comma, dot, space) in [
+        (0, "0", "0", "0"),
+        (999, "999", "999", "999"),
+        (1000, "1,000", "1.000", "1 000"),
+        (123456, "123,456", "123.456", "123 456"),
+        (1234567, "1,234,567", "1.234.567", "1 234 567"),
+    ] {
+        assert_eq!(format_number(num), comma);
+        assert_eq!(format_number_with(num, ','), comma);
+        assert_eq!(format_number_with(num, '.'), dot);
+        assert_eq!(format_number_with(num, ' '), space);
+    }
+    // 複数バイトの区切り文字も使える
+    assert_eq!(format_number_with(1000, '\u{202f}'), "1\u{202f}000");
+}
+
+#[test]
+fn test_dedup_blocks() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("a.rs"), "// generated\nfn a() {}\n").unwrap();
+    fs::write(temp_dir.path().join("b.rs"), "// generated\nfn a() {}\n").unwrap();
+    fs::write(temp_dir.path().join("c.rs"), "fn c() {}\n").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .dedup_blocks(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    for name in ["a.rs", "b.rs", "c.rs"] {
+        processor.process_path(&temp_dir.path().join(name)).unwrap();
+    }
+
+    // 2つ目の同一ファイルは最初のファイルへの参照になる
+    assert_eq!(
+        processor.get_result(),
+        "```a.rs\n// generated\nfn a() {}\n```\n\n\
+         ```b.rs\n// (identical content as a.rs)\n```\n\n\
+         ```c.rs\nfn c() {}\n```\n"
+    );
+    assert_eq!(processor.get_target_files().len(), 3);
+}
+
+#[test]
+fn test_dedup_blocks_after_sort() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("a.rs"), "// generated\n").unwrap();
+    fs::write(temp_dir.path().join("b.rs"), "// generated\n").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .dedup_blocks(true)
+        .sort(SortKey::Path)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    // 処理順とは逆に並べ替えられても、参照先は先に出力されるブロックになる
+    for name in ["b.rs", "a.rs"] {
+        processor.process_path(&temp_dir.path().join(name)).unwrap();
+    }
+
+    assert_eq!(
+        processor.get_result(),
+        "```a.rs\n// generated\n```\n\n\
+         ```b.rs\n// (identical content as a.rs)\n```\n"
+    );
+}
+
+#[test]
+fn test_stats_json() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("lib.rs"), "pub fn lib() {}").unwrap();
+    fs::write(temp_dir.path().join("big.txt"), "x".repeat(100)).unwrap();
+
+    let mut processor = CflBuilder::new()
+        .max_size(50)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let json = crate::format::stats_json(
+        &processor.stats(),
+        processor.get_target_files(),
+        processor.get_skipped_files(),
+    );
+    let stats: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+    // 処理したファイルと一致する
+    let mut paths: Vec<&str> = stats["files"].as_array().unwrap().iter().map(|f| f["path"].as_str().unwrap()).collect();
+    paths.sort();
+    assert_eq!(paths, vec!["lib.rs", "main.rs"]);
+    assert_eq!(stats["total_files"], 2);
+    assert_eq!(stats["total_size"], 27);
+    assert_eq!(stats["total_tokens"], processor.get_total_tokens());
+    assert_eq!(stats["languages"]["rust"], 2);
+    assert_eq!(stats["skipped"][0]["path"], "big.txt");
+    assert_eq!(stats["skipped"][0]["reason"], "too_large");
+    assert_eq!(stats["errors"].as_array().unwrap().len(), 0);
+}
+
+#[test]
+fn test_no_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join("secret_project")).unwrap();
+    fs::write(temp_dir.path().join("secret_project").join("main.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("helper.py"), "def helper(): pass").unwrap();
+
+    let mut processor = CflBuilder::new()
+        .no_paths(true)
+        .current_dir(temp_dir.path())
+        .build()
+        .unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // パスは出力されず、言語付きのフェンスと内容だけが残る
+    let result = processor.get_result();
+    for path in ["secret_project", "main.rs", "helper.py"] {
+        assert!(!result.contains(path), "{}: {}", path, result);
+    }
+    assert!(result.contains("```rust\nfn main() {}\n```\n"), "{}", result);
+    assert!(result.contains("```python\ndef helper(): pass\n```\n"), "{}", result);
+}
+
+#[test]
+fn test_path_comment() {
+    let opts = FormatOptions {
+        path_comment: true,
+        ..FormatOptions::default()
+    };
+
+    // 言語ごとのコメント記法でパスが先頭行に入る
+    assert_eq!(
+        format_content("src/main.rs", "fn main() {}", &opts),
+        "```src/main.rs\n// src/main.rs\nfn main() {}\n```\n"
+    );
+    assert_eq!(
+        format_content("app/helper.py", "def helper(): pass", &opts),
+        "```app/helper.py\n# app/helper.py\ndef helper(): pass\n```\n"
+    );
+    // シバンの後に挿入する
+    assert_eq!(
+        format_content("run.sh", "#!/bin/sh\necho hi\n", &opts),
+        "```run.sh\n#!/bin/sh\n# run.sh\necho hi\n```\n"
+    );
+    // コメントのない言語はそのまま
+    assert_eq!(
+        format_content("data.json", "{}", &opts),
+        "```data.json\n{}\n```\n"
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_round_trip() {
+    use crate::Stats;
+
+    let file = FileInfo {
+        path: "src/main.rs".to_string(),
+        size: 12,
+        tokens: 5,
+        hash: Some("1a2b3c4d".to_string()),
+        mime: None,
+    };
+    let json = serde_json::to_string(&file).unwrap();
+    assert_eq!(
+        json,
+        r#"{"path":"src/main.rs","size":12,"tokens":5,"hash":"1a2b3c4d","mime":null}"#
+    );
+    let parsed: FileInfo = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.path, file.path);
+    assert_eq!(parsed.size, file.size);
+    assert_eq!(parsed.tokens, file.tokens);
+    assert_eq!(parsed.hash, file.hash);
+    assert_eq!(parsed.mime, file.mime);
+
+    let stats = Stats {
+        files: 1,
+        total_size: 12,
+        total_tokens: 5,
+        languages: [("rust".to_string(), 1)].into_iter().collect(),
+    };
+    let json = serde_json::to_string(&stats).unwrap();
+    assert_eq!(
+        json,
+        r#"{"files":1,"total_size":12,"total_tokens":5,"languages":{"rust":1}}"#
+    );
+    assert_eq!(serde_json::from_str::<Stats>(&json).unwrap(), stats);
+}
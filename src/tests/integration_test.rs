@@ -1,5 +1,5 @@
 // src/tests/integration_test.rs
-use cfl::{copy_files, copy_files_with_patterns};
+use crate::{copy_files, copy_files_with_patterns};
 use std::fs;
 use tempfile::TempDir;
 
@@ -1,3 +1,7 @@
 mod processor_test;
 mod builder_test;
-mod integration_test;
\ No newline at end of file
+mod integration_test;
+mod format_test;
+mod cli_test;
+mod clipboard_test;
+mod watch_test;
\ No newline at end of file
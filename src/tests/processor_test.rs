@@ -138,4 +138,752 @@ fn test_directory_structure() {
     assert!(!structure.contains("config.json"));
     assert!(!structure.contains(".git"));
     assert!(!structure.contains(".gitignore"));
-}
\ No newline at end of file
+}
+
+/// 記録されたログを保持するテスト用ロガー
+struct CapturingLogger {
+    records: std::sync::Mutex<Vec<(log::Level, String)>>,
+}
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.records
+            .lock()
+            .unwrap()
+            .push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static CAPTURING_LOGGER: CapturingLogger = CapturingLogger {
+    records: std::sync::Mutex::new(Vec::new()),
+};
+
+#[test]
+fn test_walk_error_is_logged_as_warning() {
+    let _ = log::set_logger(&CAPTURING_LOGGER);
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join(".git")).unwrap();
+    // 不正なglobを含む.gitignoreはウォーク中のエラーになる
+    fs::write(temp_dir.path().join(".gitignore"), "{broken\n").unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+
+    let mut processor = FileProcessor::new(
+        &None,
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let gitignore = temp_dir.path().join(".gitignore").display().to_string();
+    let records = CAPTURING_LOGGER.records.lock().unwrap();
+    assert!(records.iter().any(|(level, message)| {
+        *level == log::Level::Warn
+            && message.starts_with("Error walking directory")
+            && message.contains(&gitignore)
+    }));
+}
+
+#[test]
+fn test_binary_file_skipped() {
+    let temp_dir = setup_test_directory();
+    fs::write(
+        temp_dir.path().join("src").join("image.png"),
+        [0x89, b'P', b'N', b'G', 0x00, 0x01, 0xff]
+    ).unwrap();
+
+    let mut processor = FileProcessor::new(
+        &None,
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    assert!(!processor.get_target_files().iter().any(|f| f.path.ends_with("image.png")));
+    let skipped = processor.get_skipped_files();
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].path, "src/image.png");
+    assert_eq!(skipped[0].reason, crate::SkipReason::Binary);
+}
+
+#[test]
+fn test_absolute_exclude_pattern() {
+    let temp_dir = setup_test_directory();
+    let root = fs::canonicalize(temp_dir.path()).unwrap();
+    fs::create_dir_all(root.join("vendor").join("lib")).unwrap();
+    fs::write(root.join("vendor").join("lib").join("dep.rs"), "fn dep() {}").unwrap();
+    fs::write(root.join("vendor").join("main.rs"), "fn vendored() {}").unwrap();
+
+    let exclude = format!("{}/vendor/**", root.display());
+    let mut processor = FileProcessor::new(
+        &None,
+        &Some(exclude),
+        &root,
+    ).unwrap();
+
+    processor.process_path(&root).unwrap();
+    let files = processor.get_target_files();
+
+    assert!(files.iter().any(|f| f.path == "src/main.rs"));
+    assert!(!files.iter().any(|f| f.path.starts_with("vendor")));
+}
+
+#[test]
+fn test_exclude_with_include_exception() {
+    let temp_dir = setup_test_directory();
+    fs::create_dir(temp_dir.path().join("docs")).unwrap();
+    fs::write(temp_dir.path().join("docs").join("index.md"), "# Index").unwrap();
+    fs::write(temp_dir.path().join("docs").join("guide.md"), "# Guide").unwrap();
+
+    let mut processor = FileProcessor::new(
+        &Some("docs/index.md".to_string()),
+        &Some("docs/**".to_string()),
+        temp_dir.path(),
+    ).unwrap();
+
+    processor.process_path(temp_dir.path()).unwrap();
+    let files = processor.get_target_files();
+
+    assert!(files.iter().any(|f| f.path == "docs/index.md"));
+    assert!(!files.iter().any(|f| f.path == "docs/guide.md"));
+    // 例外パターンは他のファイルを絞り込まない
+    assert!(files.iter().any(|f| f.path == "src/main.rs"));
+}
+
+#[test]
+fn test_process_paths_reports_per_path_results() {
+    let temp_dir = setup_test_directory();
+    let missing = temp_dir.path().join("missing");
+    let src = temp_dir.path().join("src");
+    let mut processor = FileProcessor::new(
+        &None,
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+
+    let results = processor
+        .process_paths(&[src.as_path(), missing.as_path()])
+        .unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].path, src);
+    assert_eq!(results[0].files_added, 2);
+    assert!(results[0].error.is_none());
+    assert_eq!(results[1].path, missing);
+    assert_eq!(results[1].files_added, 0);
+    assert!(results[1].error.is_some());
+    assert_eq!(processor.get_target_files().len(), 2);
+}
+
+#[test]
+fn test_would_include() {
+    use crate::{Inclusion, SkipReason};
+
+    let temp_dir = setup_test_directory();
+    let processor = FileProcessor::new(
+        &None,
+        &Some("test.rs".to_string()),
+        temp_dir.path(),
+    ).unwrap();
+
+    assert_eq!(
+        processor.would_include(&temp_dir.path().join("src").join("main.rs")).unwrap(),
+        Inclusion::Included
+    );
+    assert_eq!(
+        processor.would_include(&temp_dir.path().join("config.json")).unwrap(),
+        Inclusion::Excluded(SkipReason::Ignored)
+    );
+    assert_eq!(
+        processor.would_include(&temp_dir.path().join("src").join("test.rs")).unwrap(),
+        Inclusion::Excluded(SkipReason::ExcludePattern)
+    );
+    assert!(processor.would_include(&temp_dir.path().join("missing.rs")).is_err());
+
+    // 無視されたディレクトリ配下のファイルも除外される
+    fs::write(temp_dir.path().join(".gitignore"), "*.json\nbuild/\n").unwrap();
+    fs::create_dir(temp_dir.path().join("build")).unwrap();
+    fs::write(temp_dir.path().join("build").join("out.rs"), "fn out() {}").unwrap();
+    assert_eq!(
+        processor.would_include(&temp_dir.path().join("build").join("out.rs")).unwrap(),
+        Inclusion::Excluded(SkipReason::Ignored)
+    );
+}
+
+#[test]
+fn test_file_list() {
+    let temp_dir = setup_test_directory();
+    let mut processor = FileProcessor::new(
+        &Some("*.rs,*.json".to_string()),
+        &Some("test.rs".to_string()),
+        temp_dir.path(),
+    ).unwrap();
+
+    processor.process_path(temp_dir.path()).unwrap();
+    let list = processor.get_file_list();
+
+    // .gitignoreで除外されたconfig.jsonと除外パターンに一致するtest.rsは含まれない
+    assert_eq!(list, "src/main.rs\n");
+    assert!(!list.contains("test.rs"));
+    assert!(!list.contains("config.json"));
+}
+
+#[test]
+fn test_top_files() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("small.rs"), "a").unwrap();
+    fs::write(temp_dir.path().join("medium.rs"), "a b c d\n").unwrap();
+    fs::write(temp_dir.path().join("large.rs"), "a b c d e f g h i j\n".repeat(10)).unwrap();
+    // トークンは少ないがサイズは大きい
+    fs::write(temp_dir.path().join("wide.rs"), "x".repeat(1_000)).unwrap();
+
+    let mut processor = FileProcessor::new(
+        &None,
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let by_tokens: Vec<&str> = processor
+        .top_files_by_tokens(2)
+        .iter()
+        .map(|f| f.path.as_str())
+        .collect();
+    assert_eq!(by_tokens, vec!["large.rs", "medium.rs"]);
+
+    let by_size: Vec<&str> = processor
+        .top_files_by_size(3)
+        .iter()
+        .map(|f| f.path.as_str())
+        .collect();
+    assert_eq!(by_size, vec!["wide.rs", "large.rs", "medium.rs"]);
+
+    let all = processor.top_files_by_size(10);
+    assert_eq!(all.len(), 4);
+    assert!(all.windows(2).all(|pair| pair[0].size >= pair[1].size));
+}
+
+#[test]
+fn test_anchored_include_pattern() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir_all(temp_dir.path().join("src")).unwrap();
+    fs::create_dir_all(temp_dir.path().join("vendor/src")).unwrap();
+    fs::write(temp_dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("vendor/src/main.rs"), "fn vendored() {}").unwrap();
+
+    let mut processor = FileProcessor::new(
+        &Some("/src/main.rs".to_string()),
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let files = processor.get_target_files();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].path, "src/main.rs");
+
+    // 固定されていないパターンはネストしたパスに一致する
+    let mut processor = FileProcessor::new(
+        &Some("*/src/main.rs".to_string()),
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    let files = processor.get_target_files();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].path, "vendor/src/main.rs");
+}
+
+#[test]
+fn test_clear() {
+    let temp_dir = setup_test_directory();
+    let mut processor = FileProcessor::new(
+        &Some("*.rs".to_string()),
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+
+    processor.process_path(temp_dir.path()).unwrap();
+    let result = processor.get_result().to_string();
+
+    // 同じパスを処理し直すと同じ結果になる
+    fs::write(temp_dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+    processor.clear();
+    assert!(processor.get_target_files().is_empty());
+    assert!(processor.get_result().is_empty());
+
+    processor.process_path(temp_dir.path()).unwrap();
+    assert_eq!(processor.get_target_files().len(), 2);
+    assert_ne!(processor.get_result(), result);
+    assert!(processor.get_result().contains("```src/main.rs\nfn main() {}\n```"));
+}
+
+#[test]
+fn test_duplicate_patterns() {
+    let temp_dir = setup_test_directory();
+    let paths = |include: &str, exclude: Option<&str>| {
+        let mut processor = FileProcessor::new(
+            &Some(include.to_string()),
+            &exclude.map(str::to_string),
+            temp_dir.path(),
+        ).unwrap();
+        processor.process_path(temp_dir.path()).unwrap();
+        processor
+            .get_target_files()
+            .iter()
+            .map(|f| f.path.clone())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(paths("*.rs,*.rs", None), paths("*.rs", None));
+    assert_eq!(paths("*.rs", Some("test.rs,test.rs")), vec!["src/main.rs"]);
+}
+
+#[test]
+fn test_overlapping_include_exclude() {
+    let temp_dir = setup_test_directory();
+
+    // 同じパターンが両方にある場合は除外が優先される
+    for pattern in ["*.rs", "src/*.rs"] {
+        let mut processor = FileProcessor::new(
+            &Some(pattern.to_string()),
+            &Some(pattern.to_string()),
+            temp_dir.path(),
+        ).unwrap();
+        processor.process_path(temp_dir.path()).unwrap();
+        assert!(processor.get_target_files().is_empty());
+    }
+}
+
+#[test]
+fn test_write_output_dir() {
+    let temp_dir = setup_test_directory();
+    let output_dir = TempDir::new().unwrap();
+    let mut processor = FileProcessor::new(
+        &Some("*.rs".to_string()),
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    processor.write_output_dir(output_dir.path()).unwrap();
+
+    // ソースファイルごとに1つの出力ファイルが作られる
+    for (file, block) in processor.get_file_blocks() {
+        let written = fs::read_to_string(output_dir.path().join(format!("{}.md", file.path))).unwrap();
+        assert_eq!(written, block);
+    }
+    assert_eq!(fs::read_dir(output_dir.path().join("src")).unwrap().count(), 2);
+    assert!(fs::read_to_string(output_dir.path().join("src/main.rs.md"))
+        .unwrap()
+        .starts_with("```src/main.rs\n"));
+}
+
+#[test]
+fn test_patterns_with_surrounding_spaces() {
+    let temp_dir = setup_test_directory();
+    let mut processor = FileProcessor::new(
+        &Some(" main.rs , test.rs ".to_string()),
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let mut paths: Vec<&str> = processor.get_target_files().iter().map(|f| f.path.as_str()).collect();
+    paths.sort();
+    assert_eq!(paths, vec!["src/main.rs", "src/test.rs"]);
+}
+
+#[test]
+fn test_trailing_comma_patterns() {
+    let temp_dir = setup_test_directory();
+
+    // 空の要素はパターンにならない（空の include は .gitignore も含む全ファイルが対象）
+    for (include, expected) in [("*.rs,", 2), (",,", 3), ("", 3), ("main.rs,,", 1)] {
+        let mut processor = FileProcessor::new(
+            &Some(include.to_string()),
+            &Some("config.json,".to_string()),
+            temp_dir.path(),
+        ).unwrap();
+        processor.process_path(temp_dir.path()).unwrap();
+        assert_eq!(processor.get_target_files().len(), expected, "include: {:?}", include);
+    }
+}
+
+#[test]
+fn test_cost_tree() {
+    let temp_dir = setup_test_directory();
+    fs::create_dir(temp_dir.path().join("src").join("util")).unwrap();
+    fs::write(temp_dir.path().join("src").join("util").join("mod.rs"), "pub fn helper() {}").unwrap();
+    let mut processor = FileProcessor::new(
+        &Some("*.rs".to_string()),
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    let tokens = |path: &str| {
+        processor.get_target_files().iter().find(|f| f.path == path).unwrap().tokens
+    };
+    let main = tokens("src/main.rs");
+    let test = tokens("src/test.rs");
+    let util = tokens("src/util/mod.rs");
+
+    // ディレクトリには配下のファイルのトークン数の合計が表示される
+    assert_eq!(
+        processor.get_cost_tree(),
+        format!(
+            "└── src/ ({} tokens)\n  └── main.rs ({} tokens)\n  └── test.rs ({} tokens)\n  └── util/ ({} tokens)\n    └── mod.rs ({} tokens)\n",
+            main + test + util,
+            main,
+            test,
+            util,
+            util
+        )
+    );
+}
+
+#[test]
+fn test_excluded_directory_is_pruned() {
+    let temp_dir = setup_test_directory();
+    let build_dir = temp_dir.path().join("build").join("out");
+    fs::create_dir_all(&build_dir).unwrap();
+    for i in 0..5 {
+        fs::write(build_dir.join(format!("gen_{}.rs", i)), "// generated").unwrap();
+    }
+
+    let mut processor = FileProcessor::new(&None, &Some("build".to_string()), temp_dir.path()).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // build/ 配下は走査されない（.gitignore, main.rs, test.rs のみ）
+    assert_eq!(processor.candidate_count(), 3);
+    assert!(processor.get_target_files().iter().all(|f| !f.path.starts_with("build/")));
+
+    // 除外されたディレクトリを直接渡しても走査しない
+    processor.clear();
+    processor.process_path(&temp_dir.path().join("build")).unwrap();
+    assert_eq!(processor.candidate_count(), 0);
+
+    // 除外ディレクトリ内の例外パターンがある場合は枝刈りしない
+    let mut processor = FileProcessor::new(
+        &Some("build/out/gen_0.rs".to_string()),
+        &Some("build".to_string()),
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    assert_eq!(processor.candidate_count(), 8);
+    let paths: Vec<&str> = processor.get_target_files().iter().map(|f| f.path.as_str()).collect();
+    assert!(paths.contains(&"build/out/gen_0.rs"));
+    assert!(!paths.contains(&"build/out/gen_1.rs"));
+}
+
+#[test]
+fn test_set_base_indent() {
+    let temp_dir = setup_test_directory();
+    fs::write(temp_dir.path().join("src").join("lib.rs"), "pub fn a() {}\n\npub fn b() {}\n").unwrap();
+    let mut processor = FileProcessor::new(
+        &Some("*.rs".to_string()),
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    let plain = processor.get_result().to_string();
+
+    // 処理済みの結果にも適用され、フェンスを含むすべての行がインデントされる
+    processor.set_base_indent("   ");
+    let indented = processor.get_result();
+    assert_eq!(indented.lines().filter(|line| line.starts_with("   ```")).count(), 6);
+    for line in indented.lines().filter(|line| !line.is_empty()) {
+        assert!(line.starts_with("   "), "{:?}", line);
+    }
+    let unindented: Vec<&str> = indented.lines().map(|line| line.strip_prefix("   ").unwrap_or(line)).collect();
+    assert_eq!(unindented, plain.lines().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_set_output_transform() {
+    let temp_dir = setup_test_directory();
+    let mut processor = FileProcessor::new(
+        &Some("*.rs".to_string()),
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+    let plain = processor.get_result().to_string();
+    let tokens = processor.get_total_tokens();
+
+    // 処理済みの結果全体に一度だけ適用される
+    processor.set_output_transform(Box::new(|output| format!("<context>\n{}</context>\n", output)));
+    assert_eq!(processor.get_result(), format!("<context>\n{}</context>\n", plain));
+    assert_eq!(processor.get_total_tokens(), tokens);
+
+    // 以降の処理でも適用される
+    processor.clear();
+    processor.process_path(temp_dir.path()).unwrap();
+    assert_eq!(processor.get_result().matches("<context>").count(), 1);
+    assert!(processor.get_result().ends_with("</context>\n"));
+}
+
+#[test]
+fn test_render_on_access() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let temp_dir = setup_test_directory();
+    let mut processor = FileProcessor::new(&None, &None, temp_dir.path()).unwrap();
+    let renders = Arc::new(AtomicUsize::new(0));
+    let counter = renders.clone();
+    processor.set_output_transform(Box::new(move |output| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        output
+    }));
+
+    // ファイルごとには描画せず、結果を読んだときに一度だけ描画する
+    for name in ["src/main.rs", "config.json"] {
+        processor.process_path(&temp_dir.path().join(name)).unwrap();
+    }
+    assert_eq!(renders.load(Ordering::SeqCst), 0);
+    let result = processor.get_result().to_string();
+    assert_eq!(processor.get_total_size(), result.len());
+    assert!(!processor.is_truncated());
+    assert_eq!(renders.load(Ordering::SeqCst), 1);
+
+    // ファイルが増えると描画し直す
+    processor.process_path(&temp_dir.path().join("src/test.rs")).unwrap();
+    assert!(processor.get_result().len() > result.len());
+    assert_eq!(renders.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_get_previews() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "// entry point\nfn main() {\n    run();\n}\n").unwrap();
+    let mut processor = FileProcessor::new(
+        &Some("*.rs".to_string()),
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // 先頭の N 行だけが含まれる
+    let previews = processor.get_previews(2);
+    assert_eq!(previews.len(), 1);
+    assert_eq!(previews[0].0.path, "main.rs");
+    assert_eq!(previews[0].1, vec!["// entry point", "fn main() {"]);
+    assert!(processor.get_previews(0)[0].1.is_empty());
+}
+
+#[test]
+fn test_crlf_normalized() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("win.txt"), "first\r\nsecond\rthird\r\n").unwrap();
+    let mut processor = FileProcessor::new(
+        &None,
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // 出力には \n だけが残り、サイズも正規化後の内容で数える
+    let result = processor.get_result();
+    assert!(!result.contains('\r'));
+    assert!(result.contains("first\nsecond\nthird\n"));
+    assert_eq!(processor.get_target_files()[0].size, "first\nsecond\nthird\n".len());
+}
+
+#[test]
+fn test_add_virtual_file() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+    let mut processor = FileProcessor::new(
+        &None,
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+    processor.process_path(temp_dir.path()).unwrap();
+
+    // ディスク上にないファイルも読み込んだファイルと同様に扱われる
+    let summary = "# Summary\nThe entry point prints nothing.\n";
+    processor.add_virtual_file("SUMMARY.md", summary);
+
+    let result = processor.get_result();
+    assert!(result.contains("```main.rs\nfn main() {}\n"));
+    assert!(result.contains(&format!("```SUMMARY.md\n{}", summary)));
+    assert!(result.find("main.rs").unwrap() < result.find("SUMMARY.md").unwrap());
+
+    let stats = processor.stats();
+    assert_eq!(stats.files, 2);
+    assert_eq!(stats.total_size, "fn main() {}\n".len() + summary.len());
+    assert_eq!(stats.languages.get("markdown"), Some(&1));
+    assert!(!temp_dir.path().join("SUMMARY.md").exists());
+}
+
+#[test]
+fn test_ignore_reason() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join(".git")).unwrap();
+    fs::create_dir_all(temp_dir.path().join("build")).unwrap();
+    fs::create_dir_all(temp_dir.path().join("logs")).unwrap();
+    fs::write(temp_dir.path().join(".gitignore"), "# generated\n*.log\nbuild/\n").unwrap();
+    fs::write(temp_dir.path().join("logs").join(".gitignore"), "!keep.log\n").unwrap();
+    fs::write(temp_dir.path().join("debug.log"), "log").unwrap();
+    fs::write(temp_dir.path().join("logs").join("keep.log"), "log").unwrap();
+    fs::write(temp_dir.path().join("build").join("out.rs"), "// out").unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+    let processor = FileProcessor::new(
+        &None,
+        &None,
+        temp_dir.path(),
+    ).unwrap();
+
+    // .gitignore のどの行で除外されたかを返す
+    let rule = processor.ignore_reason(&temp_dir.path().join("debug.log")).unwrap().unwrap();
+    assert_eq!(rule.file, temp_dir.path().join(".gitignore"));
+    assert_eq!(rule.line, 2);
+    assert_eq!(rule.pattern, "*.log");
+
+    // 親ディレクトリに一致するルールも報告される
+    let rule = processor.ignore_reason(&temp_dir.path().join("build").join("out.rs")).unwrap().unwrap();
+    assert_eq!((rule.line, rule.pattern.as_str()), (3, "build/"));
+
+    // より深い階層の否定ルールが優先される
+    assert_eq!(processor.ignore_reason(&temp_dir.path().join("logs").join("keep.log")).unwrap(), None);
+    assert_eq!(processor.ignore_reason(&temp_dir.path().join("main.rs")).unwrap(), None);
+}
+
+#[test]
+fn test_explicit_file_bypasses_gitignore() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir(temp_dir.path().join(".git")).unwrap();
+    fs::create_dir(temp_dir.path().join("vendor")).unwrap();
+    fs::write(temp_dir.path().join(".gitignore"), "*.log\nvendor/\n").unwrap();
+    fs::write(temp_dir.path().join("debug.log"), "started").unwrap();
+    fs::write(temp_dir.path().join("vendor").join("lib.rs"), "pub fn lib() {}").unwrap();
+
+    let mut processor = FileProcessor::new(
+        &None,
+        &Some("vendor".to_string()),
+        temp_dir.path(),
+    ).unwrap();
+
+    // ディレクトリの走査では gitignore により除外される
+    processor.process_path(temp_dir.path()).unwrap();
+    assert!(!processor.get_target_files().iter().any(|f| f.path == "debug.log"));
+
+    // 明示的に指定したファイルは gitignore に一致しても含まれる
+    processor.clear();
+    processor.process_path(&temp_dir.path().join("debug.log")).unwrap();
+    let files = processor.get_target_files();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].path, "debug.log");
+
+    // 除外パターンは引き続き適用される
+    processor.process_path(&temp_dir.path().join("vendor").join("lib.rs")).unwrap();
+    assert_eq!(processor.get_target_files().len(), 1);
+}
+
+/// 途中まで読めた後にエラーを返す、書き込み中のファイルを模したリーダー
+struct FlakyReader {
+    data: Vec<u8>,
+    position: usize,
+    fail_after: Option<usize>,
+}
+
+impl std::io::Read for FlakyReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.fail_after.is_some_and(|limit| self.position >= limit) {
+            return Err(std::io::Error::other("file truncated while reading"));
+        }
+        let end = (self.position + buf.len().min(4)).min(self.data.len());
+        let count = end - self.position;
+        buf[..count].copy_from_slice(&self.data[self.position..end]);
+        self.position = end;
+        Ok(count)
+    }
+}
+
+#[test]
+fn test_read_limited() {
+    use crate::processor::read_limited;
+
+    let reader = |data: &[u8], fail_after| FlakyReader { data: data.to_vec(), position: 0, fail_after };
+
+    // 途中で失敗した読み込みは部分的な内容ではなくエラーになる
+    assert!(read_limited(reader(b"fn main() {}", Some(5)), None).is_err());
+
+    // サイズは読み込んだ内容の長さで決まる
+    let bytes = read_limited(reader(b"fn main() {}", None), Some(100)).unwrap().unwrap();
+    assert_eq!(bytes, b"fn main() {}");
+
+    // 事前の確認後に大きくなったファイルも上限で打ち切られる
+    assert_eq!(read_limited(reader(&[b'a'; 101], None), Some(100)).unwrap(), None);
+    assert_eq!(read_limited(reader(&[b'a'; 100], None), Some(100)).unwrap().unwrap().len(), 100);
+}
+
+#[test]
+fn test_pattern_dir() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir_all(temp_dir.path().join(".cfl")).unwrap();
+    fs::create_dir_all(temp_dir.path().join("vendor/lib")).unwrap();
+    fs::write(temp_dir.path().join(".cfl/generated.ignore"), "# 生成されたファイル\n*.pb.rs\n").unwrap();
+    fs::write(temp_dir.path().join(".cfl/vendor.ignore"), "vendor\n").unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("api.pb.rs"), "struct Api;").unwrap();
+    fs::write(temp_dir.path().join("vendor/lib/dep.rs"), "fn dep() {}").unwrap();
+
+    let process = |include: Option<&str>| {
+        let include = include.map(str::to_string);
+        let mut processor = FileProcessor::new(&include, &None, temp_dir.path()).unwrap();
+        processor.process_path(temp_dir.path()).unwrap();
+        let mut paths: Vec<_> = processor.get_target_files().iter().map(|f| f.path.clone()).collect();
+        paths.sort();
+        paths
+    };
+
+    // 両方の .ignore ファイルのパターンが適用される
+    assert_eq!(process(Some("*.rs")), vec!["main.rs"]);
+
+    // .include ファイルのパターンは -i のパターンと和集合になる
+    fs::write(temp_dir.path().join(".cfl/docs.include"), "*.md\n").unwrap();
+    fs::write(temp_dir.path().join("README.md"), "# Readme").unwrap();
+    assert_eq!(process(None), vec!["README.md"]);
+    assert_eq!(process(Some("*.rs")), vec!["README.md", "main.rs"]);
+}
+
+#[test]
+fn test_name_include_with_path_exclude() {
+    let temp_dir = TempDir::new().unwrap();
+    fs::create_dir_all(temp_dir.path().join("generated/api")).unwrap();
+    fs::create_dir_all(temp_dir.path().join("src/generated")).unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}").unwrap();
+    fs::write(temp_dir.path().join("generated/api/client.rs"), "// generated").unwrap();
+    fs::write(temp_dir.path().join("generated.rs"), "// not a directory").unwrap();
+    fs::write(temp_dir.path().join("src/generated/model.rs"), "// generated").unwrap();
+    fs::write(temp_dir.path().join("src/lib.py"), "pass").unwrap();
+
+    let process = |exclude: &str| {
+        let mut processor = FileProcessor::new(
+            &Some("*.rs".to_string()),
+            &Some(exclude.to_string()),
+            temp_dir.path(),
+        ).unwrap();
+        processor.process_path(temp_dir.path()).unwrap();
+        let mut paths: Vec<_> = processor.get_target_files().iter().map(|f| f.path.clone()).collect();
+        paths.sort();
+        paths
+    };
+
+    // "*.rs" はファイル名に、"generated/**" は相対パスに一致する
+    assert_eq!(
+        process("generated/**"),
+        vec!["generated.rs", "main.rs", "src/generated/model.rs"]
+    );
+    // どの階層の generated/ も除外するには "**/" を付ける
+    assert_eq!(process("**/generated/**"), vec!["generated.rs", "main.rs"]);
+}
@@ -0,0 +1,38 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::watch::{is_relevant, Debouncer};
+
+#[test]
+fn test_debounced_rerun() {
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    let mut debouncer = Debouncer::new(Duration::from_millis(200));
+
+    // イベントがなければ再実行しない
+    assert!(!debouncer.should_run(at(500)));
+
+    // 連続したイベントは最後のイベントから待つ
+    debouncer.event(at(0));
+    debouncer.event(at(100));
+    debouncer.event(at(150));
+    assert!(!debouncer.should_run(at(300)));
+    assert!(debouncer.should_run(at(350)));
+    assert!(!debouncer.should_run(at(400)));
+
+    debouncer.event(at(1_000));
+    assert!(debouncer.should_run(at(1_200)));
+}
+
+#[test]
+fn test_relevant_changes() {
+    assert!(is_relevant(Path::new("/repo/src/main.rs"), &[]));
+    assert!(!is_relevant(Path::new("/repo/.git/index"), &[]));
+
+    // 実行自身が書き込むファイルやディレクトリへの変更は無視する
+    let outputs = [PathBuf::from("/repo/out.md"), PathBuf::from("/repo/blocks")];
+    assert!(!is_relevant(Path::new("/repo/out.md"), &outputs));
+    assert!(!is_relevant(Path::new("/repo/blocks/src/main.rs.md"), &outputs));
+    assert!(is_relevant(Path::new("/repo/out.md.bak"), &outputs));
+    assert!(is_relevant(Path::new("/repo/src/main.rs"), &outputs));
+}
@@ -0,0 +1,181 @@
+use crate::processor::TokenModel;
+use std::path::Path;
+
+/// Import syntax for a language: line prefixes that start an import statement
+/// and the comment marker used for the collapsed placeholder
+struct ImportSyntax {
+    prefixes: &'static [&'static str],
+    comment: &'static str,
+    /// Whether a statement only ends at a `;` (Rust)
+    needs_semicolon: bool,
+}
+
+fn import_syntax(path: &Path) -> Option<ImportSyntax> {
+    let extension = path.extension()?.to_str()?;
+    match extension {
+        "rs" => Some(ImportSyntax {
+            prefixes: &["use "],
+            comment: "//",
+            needs_semicolon: true,
+        }),
+        "py" => Some(ImportSyntax {
+            prefixes: &["import ", "from "],
+            comment: "#",
+            needs_semicolon: false,
+        }),
+        "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" => Some(ImportSyntax {
+            prefixes: &["import "],
+            comment: "//",
+            needs_semicolon: false,
+        }),
+        _ => None,
+    }
+}
+
+/// Replace runs of import statements with a single `// N imports collapsed` line.
+///
+/// Only lines that start with an import keyword are considered, so code that merely
+/// mentions `use` or `import` elsewhere is left untouched. Files in unsupported
+/// languages are returned unchanged.
+pub(crate) fn collapse_imports(path: &Path, content: &str) -> String {
+    let syntax = match import_syntax(path) {
+        Some(syntax) => syntax,
+        None => return content.to_string(),
+    };
+
+    let lines: Vec<&str> = content.lines().collect();
+    let mut output: Vec<String> = Vec::with_capacity(lines.len());
+    let mut index = 0;
+
+    while index < lines.len() {
+        let mut count = 0;
+        let mut end = index;
+        let mut cursor = index;
+
+        // 空行を挟んだ連続するimport文をひとまとめにする
+        loop {
+            while cursor < lines.len() && lines[cursor].trim().is_empty() && count > 0 {
+                cursor += 1;
+            }
+            match import_statement_end(&lines, cursor, &syntax) {
+                Some(statement_end) => {
+                    count += 1;
+                    cursor = statement_end + 1;
+                    end = cursor;
+                }
+                None => break,
+            }
+        }
+
+        if count == 0 {
+            output.push(lines[index].to_string());
+            index += 1;
+            continue;
+        }
+
+        let indent = &lines[index][..lines[index].len() - lines[index].trim_start().len()];
+        let noun = if count == 1 { "import" } else { "imports" };
+        output.push(format!(
+            "{}{} {} {} collapsed",
+            indent, syntax.comment, count, noun
+        ));
+        index = end;
+    }
+
+    let mut collapsed = output.join("\n");
+    if content.ends_with('\n') {
+        collapsed.push('\n');
+    }
+    collapsed
+}
+
+/// If an import statement starts at `start`, return the index of its last line
+fn import_statement_end(lines: &[&str], start: usize, syntax: &ImportSyntax) -> Option<usize> {
+    let first = lines.get(start)?.trim_start();
+    if !syntax
+        .prefixes
+        .iter()
+        .any(|prefix| first.starts_with(prefix))
+    {
+        return None;
+    }
+    // Python の `from x` は `import` を伴う場合のみ対象
+    if first.starts_with("from ") && !first.contains(" import ") && !first.ends_with(" import") {
+        return None;
+    }
+
+    let mut depth: i32 = 0;
+    for (offset, line) in lines[start..].iter().enumerate() {
+        depth += line.matches(['{', '(', '[']).count() as i32;
+        depth -= line.matches(['}', ')', ']']).count() as i32;
+        let terminated = !syntax.needs_semicolon || line.trim_end().ends_with(';');
+        if depth <= 0 && terminated {
+            return Some(start + offset);
+        }
+    }
+
+    None
+}
+
+/// Convert `\r\n` and lone `\r` line endings to `\n`
+pub(crate) fn normalize_line_endings(content: &str) -> String {
+    content.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+/// Check whether any line is longer than `max_length` characters
+pub(crate) fn has_long_line(content: &str, max_length: usize) -> bool {
+    content
+        .lines()
+        .any(|line| line.chars().count() > max_length)
+}
+
+/// Cut lines longer than `max_length` characters, marking how much was removed
+pub(crate) fn truncate_long_lines(content: &str, max_length: usize) -> String {
+    let mut truncated = content
+        .lines()
+        .map(|line| {
+            let length = line.chars().count();
+            if length <= max_length {
+                return line.to_string();
+            }
+            let kept: String = line.chars().take(max_length).collect();
+            format!("{} [... {} chars truncated]", kept, length - max_length)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if content.ends_with('\n') {
+        truncated.push('\n');
+    }
+    truncated
+}
+
+/// Keep whole lines from the start while they fit in `max_tokens` as estimated for `model`,
+/// marking how many lines were cut. Content within the budget is returned unchanged.
+pub(crate) fn truncate_to_tokens(content: &str, max_tokens: usize, model: TokenModel) -> String {
+    if model.estimate(content) <= max_tokens {
+        return content.to_string();
+    }
+
+    let lines: Vec<&str> = content.lines().collect();
+    let mut tokens = 0;
+    let mut kept = 0;
+    for line in &lines {
+        let line_tokens = model.estimate(line);
+        if tokens + line_tokens > max_tokens {
+            break;
+        }
+        tokens += line_tokens;
+        kept += 1;
+    }
+
+    let mut truncated = String::new();
+    for line in &lines[..kept] {
+        truncated.push_str(line);
+        truncated.push('\n');
+    }
+    truncated.push_str(&format!(
+        "[... {} more lines truncated]\n",
+        lines.len() - kept
+    ));
+    truncated
+}
@@ -0,0 +1,92 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Decides when to re-run after a burst of file change events.
+///
+/// A re-run is due once no new event has arrived for the configured delay, so saving
+/// several files at once triggers a single run.
+#[derive(Clone, Debug)]
+pub struct Debouncer {
+    delay: Duration,
+    last_event: Option<Instant>,
+}
+
+impl Debouncer {
+    /// Create a debouncer waiting `delay` after the last event
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            last_event: None,
+        }
+    }
+
+    /// Record a change event that happened at `now`
+    pub fn event(&mut self, now: Instant) {
+        self.last_event = Some(now);
+    }
+
+    /// Whether a re-run is due at `now`. Returns `true` once per burst of events.
+    pub fn should_run(&mut self, now: Instant) -> bool {
+        match self.last_event {
+            Some(last) if now.duration_since(last) >= self.delay => {
+                self.last_event = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether a change to `path` can affect the output: changes inside `.git` can't, nor can
+/// changes to the `outputs` (canonical paths of the files and directories the run writes)
+pub fn is_relevant(path: &Path, outputs: &[PathBuf]) -> bool {
+    !path
+        .components()
+        .any(|component| component.as_os_str() == ".git")
+        && !outputs.iter().any(|output| path.starts_with(output))
+}
+
+/// Watch `paths` recursively and call `on_change` after each debounced burst of changes,
+/// ignoring changes to `outputs` (see [`is_relevant`]).
+///
+/// Runs until the watcher stops or `on_change` returns an error.
+#[cfg(feature = "watch")]
+pub fn watch(
+    paths: &[&Path],
+    outputs: &[PathBuf],
+    delay: Duration,
+    mut on_change: impl FnMut() -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    use notify::{EventKind, RecursiveMode, Watcher};
+    use std::sync::mpsc::{self, RecvTimeoutError};
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    for path in paths {
+        // イベントのパスを出力のパスと比較できるよう、正規化したパスを監視する
+        watcher.watch(&path.canonicalize()?, RecursiveMode::Recursive)?;
+    }
+
+    let mut debouncer = Debouncer::new(delay);
+    loop {
+        match receiver.recv_timeout(delay) {
+            Ok(Ok(event)) => {
+                // 読み込みによるアクセスイベントで再実行を繰り返さないようにする
+                let changed = matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                );
+                if changed && event.paths.iter().any(|path| is_relevant(path, outputs)) {
+                    debouncer.event(Instant::now());
+                }
+            }
+            Ok(Err(err)) => log::warn!("Error watching files: {}", err),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        if debouncer.should_run(Instant::now()) {
+            on_change()?;
+        }
+    }
+}